@@ -1,5 +1,7 @@
 extern crate term_cursor as cursor;
 
+use std::io::{self, Write};
+
 #[derive(Clone, Copy)]
 struct Rect {
     x: i32,
@@ -20,11 +22,15 @@ impl Rect {
 }
 
 fn main() {
-    print!("{}", cursor::Clear);
+    // Draw on the alternate screen, so the contents of the terminal are restored on exit.
+    let screen = cursor::AlternateScreen::enter().expect("Entering the alternate screen failed");
     window(Rect::new(1, 1, 50, 20), "Window title", "Window content");
 
-    // Move the cursor down under the window, so the command prompt doesn't draw into the window.
-    print!("{}", cursor::Goto(0, 26));
+    print!("{}Press enter to exit.", cursor::Goto(0, 23));
+    io::stdout().flush().expect("Flushing stdout failed");
+    let _ = io::stdin().read_line(&mut String::new());
+
+    drop(screen);
 }
 
 fn window(rect: Rect, title: &str, content: &str) {
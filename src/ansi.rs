@@ -0,0 +1,131 @@
+//! The raw ANSI escape sequences, shared between the `Display` types and `Terminal`.
+
+use std::fmt::{Display, Formatter, Result as FmtResult, Write};
+
+use super::ClearMode;
+
+/// `CUP`, moves the cursor to the specified zero-based coordinates.
+pub struct CursorPos(pub i32, pub i32);
+
+impl Display for CursorPos {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        // CUP is one-based
+        write!(
+            fmt,
+            "\x1B[{};{}H",
+            self.1.saturating_add(1),
+            self.0.saturating_add(1)
+        )
+    }
+}
+
+/// `CUU` / `CUD` / `CUF` / `CUB`, moves the cursor by the specified amounts.
+pub struct CursorMove(pub i32, pub i32);
+
+impl Display for CursorMove {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        // A parameter of 0 means 1, so nothing must be written for no movement at all
+        let CursorMove(x, y) = *self;
+        match x {
+            x if x > 0 => write!(fmt, "\x1B[{}C", x)?,
+            x if x < 0 => write!(fmt, "\x1B[{}D", x.unsigned_abs())?,
+            _ => {}
+        }
+        match y {
+            y if y > 0 => write!(fmt, "\x1B[{}B", y),
+            y if y < 0 => write!(fmt, "\x1B[{}A", y.unsigned_abs()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// `CHA`, moves the cursor to the specified zero-based column. Negative columns are clamped to 0.
+pub struct CursorColumn(pub i32);
+
+impl Display for CursorColumn {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "\x1B[{}G", self.0.max(0).saturating_add(1))
+    }
+}
+
+/// `VPA`, moves the cursor to the specified zero-based row. Negative rows are clamped to 0.
+pub struct CursorRow(pub i32);
+
+impl Display for CursorRow {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "\x1B[{}d", self.0.max(0).saturating_add(1))
+    }
+}
+
+/// `CNL` / `CPL`, moves the cursor to the start of the line the specified amount of lines down.
+pub struct CursorNextLine(pub i32);
+
+impl Display for CursorNextLine {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match self.0 {
+            n if n > 0 => write!(fmt, "\x1B[{}E", n),
+            n if n < 0 => write!(fmt, "\x1B[{}F", n.unsigned_abs()),
+            _ => write!(fmt, "{}", CursorColumn(0)),
+        }
+    }
+}
+
+/// `DSR`, requests a cursor position report.
+pub const REQUEST_CURSOR_POS: &str = "\x1B[6n";
+
+#[cfg(unix)]
+/// `DECRQSS`, requests the current attributes of the text (`SGR`).
+pub const REQUEST_SGR: &str = "\x1BP$qm\x1B\\";
+
+#[cfg(unix)]
+/// Sets an RGB text color and requests it back with `DECRQSS`.
+///
+/// The color is chosen so that it does not appear by accident in a reply of a terminal, that approximates it.
+/// The previous attributes of the text have to be restored afterwards.
+pub const PROBE_TRUECOLOR: &str = "\x1B[38;2;10;20;30m\x1BP$qm\x1B\\";
+
+/// `ED` / `EL`, erases the cells selected by `mode`.
+pub fn clear(mode: ClearMode) -> &'static str {
+    match mode {
+        ClearMode::All => "\x1B[2J",
+        ClearMode::Below => "\x1B[0J",
+        ClearMode::Above => "\x1B[1J",
+        ClearMode::Scrollback => "\x1B[3J",
+        ClearMode::Line => "\x1B[2K",
+        ClearMode::LineLeft => "\x1B[1K",
+        ClearMode::LineRight => "\x1B[0K",
+    }
+}
+
+/// `OSC 0` / `OSC 1` / `OSC 2`, sets the title and icon name / the icon name / the title of the window.
+///
+/// Control characters are left out of the text, so it can not end the sequence early and inject other sequences.
+pub struct Title<'a>(pub u8, pub &'a str);
+
+impl<'a> Display for Title<'a> {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "\x1B]{};", self.0)?;
+        for c in self.1.chars().filter(|c| !c.is_control()) {
+            fmt.write_char(c)?;
+        }
+        fmt.write_str("\x1B\\")
+    }
+}
+
+/// `XTWINOPS` 22, saves the title and icon name of the window on a stack.
+pub const PUSH_TITLE: &str = "\x1B[22;0t";
+
+/// `XTWINOPS` 23, restores the title and icon name of the window saved by `PUSH_TITLE`.
+pub const POP_TITLE: &str = "\x1B[23;0t";
+
+/// `DECSC`, saves the cursor position.
+pub const SAVE_POS: &str = "\x1B7";
+
+/// `DECRC`, restores the cursor position saved by `DECSC`.
+pub const RESTORE_POS: &str = "\x1B8";
+
+/// `DECTCEM` reset, hides the cursor.
+pub const HIDE: &str = "\x1B[?25l";
+
+/// `DECTCEM` set, shows the cursor.
+pub const SHOW: &str = "\x1B[?25h";
@@ -0,0 +1,324 @@
+//! Detection of the features the terminal supports beyond the basic ANSI sequences.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use platform;
+
+/// The colors a terminal is able to display.
+///
+/// The variants are ordered from the least to the most colors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ColorSupport {
+    /// No colors at all, e.g. because the output is not a terminal or the user asked for no colors.
+    NoColor,
+    /// The 16 colors of the terminal's palette.
+    Ansi16,
+    /// The 256 color palette.
+    Ansi256,
+    /// 24-bit RGB colors.
+    TrueColor,
+}
+
+/// The features of the terminal, that are only used by this crate if they are supported.
+///
+/// Sequences for unsupported features are degraded to a supported alternative, or left out,
+/// instead of leaving the terminal to print garbage or misinterpret them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Capabilities {
+    /// The colors that are supported. Colors the terminal can not display are replaced by the nearest supported one.
+    pub colors: ColorSupport,
+    /// Whether the extended underline styles (curly, dotted, ...) and underline colors are supported.
+    pub extended_underline: bool,
+    /// Whether hyperlinks are supported.
+    pub hyperlinks: bool,
+}
+
+impl Capabilities {
+    /// Capabilities that only include what virtually every terminal supports.
+    pub fn basic() -> Self {
+        Capabilities {
+            colors: ColorSupport::Ansi16,
+            extended_underline: false,
+            hyperlinks: false,
+        }
+    }
+
+    /// Detect the capabilities of the terminal from the environment of the process.
+    ///
+    /// The supported colors are taken from `COLORTERM`, `TERM` and the `colors` entry of the terminfo database.
+    /// Colors are disabled if `NO_COLOR` is set or `stdout` is not a terminal,
+    /// unless `CLICOLOR_FORCE` is set (to anything but `0`). Hyperlinks are disabled as well if `stdout` is not a terminal.
+    ///
+    /// The detected capabilities apply to `stdout`. When the output goes to a different terminal,
+    /// like a `Terminal::tty` while `stdout` is piped into `less`, detect them with `CLICOLOR_FORCE`
+    /// or set them yourself with `set_capabilities`.
+    pub fn detect() -> Self {
+        let term = env::var("TERM").unwrap_or_default();
+        let program = env::var("TERM_PROGRAM").unwrap_or_default();
+        Capabilities {
+            colors: detect_colors(&term, &program),
+            extended_underline: detect_extended_underline(&term, &program),
+            hyperlinks: detect_hyperlinks(&term, &program),
+        }
+    }
+
+    /// Like `detect`, but additionally ask the terminal whether it supports RGB colors, if the environment does not tell.
+    ///
+    /// The terminal gets `timeout` to reply, see `Terminal::probe_truecolor`.
+    pub fn detect_with_probe(timeout: Duration) -> Self {
+        let mut caps = Capabilities::detect();
+        if caps.colors > ColorSupport::NoColor
+            && caps.colors < ColorSupport::TrueColor
+            && platform::probe_truecolor(timeout).unwrap_or(false)
+        {
+            caps.colors = ColorSupport::TrueColor;
+        }
+        caps
+    }
+}
+
+fn detect_colors(term: &str, program: &str) -> ColorSupport {
+    // See https://no-color.org
+    if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        return ColorSupport::NoColor;
+    }
+    let forced = env::var_os("CLICOLOR_FORCE").is_some_and(|v| !v.is_empty() && v != "0");
+    if !forced && (term == "dumb" || !platform::stdout_is_tty()) {
+        return ColorSupport::NoColor;
+    }
+
+    let colorterm = env::var("COLORTERM").unwrap_or_default();
+    let colors = if colorterm == "truecolor"
+        || colorterm == "24bit"
+        || term.ends_with("-direct")
+        || ["iTerm.app", "WezTerm", "ghostty", "vscode"].contains(&program)
+        || env::var_os("WT_SESSION").is_some()
+    {
+        ColorSupport::TrueColor
+    } else {
+        match terminfo_colors(term) {
+            Some(n) if n >= 1 << 24 => ColorSupport::TrueColor,
+            Some(n) if n >= 256 => ColorSupport::Ansi256,
+            Some(n) if n >= 8 => ColorSupport::Ansi16,
+            Some(_) => ColorSupport::NoColor,
+            None if term.contains("256color") => ColorSupport::Ansi256,
+            None => ColorSupport::Ansi16,
+        }
+    };
+    if forced {
+        colors.max(ColorSupport::Ansi16)
+    } else {
+        colors
+    }
+}
+
+// Look up the number of colors of `term` in the terminfo database.
+// Returns `None` if there is no entry for `term`, and `Some(0)` if the entry has no colors.
+fn terminfo_colors(term: &str) -> Option<i32> {
+    let first = term.chars().next()?;
+    if term.contains('/') {
+        return None;
+    }
+    // Entries are grouped by their first character, or its hex code on macOS
+    let subdirs = [first.to_string(), format!("{:x}", first as u32)];
+    let data = terminfo_dirs()
+        .iter()
+        .flat_map(|dir| subdirs.iter().map(move |sub| dir.join(sub).join(term)))
+        .filter_map(|path| fs::read(path).ok())
+        .next()?;
+    parse_terminfo_colors(&data)
+}
+
+fn terminfo_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(dir) = env::var_os("TERMINFO") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Some(home) = env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    if let Some(list) = env::var_os("TERMINFO_DIRS") {
+        dirs.extend(env::split_paths(&list).filter(|dir| !dir.as_os_str().is_empty()));
+    }
+    for dir in &[
+        "/etc/terminfo",
+        "/lib/terminfo",
+        "/usr/share/terminfo",
+        "/usr/lib/terminfo",
+        "/usr/share/lib/terminfo",
+        "/usr/local/share/terminfo",
+    ] {
+        dirs.push(PathBuf::from(dir));
+    }
+    dirs
+}
+
+// Read the `colors` number out of a compiled terminfo entry (see `term(5)`).
+fn parse_terminfo_colors(data: &[u8]) -> Option<i32> {
+    // The index of `colors` in the numbers section
+    const COLORS: usize = 13;
+
+    let short = |at: usize| {
+        data.get(at..at + 2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+    };
+    let number_size = match short(0)? {
+        0o432 => 2,
+        // The extended format of ncurses 6.1, with 32-bit numbers
+        0o1036 => 4,
+        _ => return None,
+    };
+    let (names_size, bools, numbers) = (short(2)?, short(4)?, short(6)?);
+    if names_size < 0 || bools < 0 {
+        return None;
+    }
+    if numbers <= COLORS as i16 {
+        return Some(0);
+    }
+
+    // The numbers section is aligned to an even offset
+    let mut at = 12 + names_size as usize + bools as usize;
+    at += at % 2;
+    at += COLORS * number_size;
+    let colors = match number_size {
+        2 => short(at)? as i32,
+        _ => data
+            .get(at..at + 4)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))?,
+    };
+    // Missing (-1) and cancelled (-2) entries are negative
+    Some(colors.max(0))
+}
+
+// Parse a version number from the environment, like `VTE_VERSION=6800` for VTE 0.68.
+fn env_version(name: &str) -> u32 {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0)
+}
+
+fn detect_extended_underline(term: &str, program: &str) -> bool {
+    ["kitty", "wezterm", "foot", "contour", "ghostty"]
+        .iter()
+        .any(|name| term.contains(name))
+        || ["WezTerm", "ghostty", "vscode"].contains(&program)
+        // VTE 0.52 (GNOME Terminal, Tilix, ...) added curly underlines and underline colors
+        || env_version("VTE_VERSION") >= 5200
+}
+
+fn detect_hyperlinks(term: &str, program: &str) -> bool {
+    // Links written to a file or pipe would only get in the way
+    if !platform::stdout_is_tty() {
+        return false;
+    }
+    ["kitty", "wezterm", "foot", "contour", "ghostty", "alacritty"]
+        .iter()
+        .any(|name| term.contains(name))
+        || ["iTerm.app", "WezTerm", "ghostty", "vscode"].contains(&program)
+        || env::var_os("WT_SESSION").is_some()
+        // VTE 0.50 and Konsole 20.12 added hyperlinks
+        || env_version("VTE_VERSION") >= 5000
+        || env_version("KONSOLE_VERSION") >= 201200
+}
+
+static CAPABILITIES: Mutex<Option<Capabilities>> = Mutex::new(None);
+
+/// Get the capabilities of the terminal, as used by the types of this crate.
+///
+/// They are detected with `Capabilities::detect` on first use, unless they were set with `set_capabilities` before.
+pub fn capabilities() -> Capabilities {
+    let mut caps = CAPABILITIES.lock().unwrap_or_else(|err| err.into_inner());
+    *caps.get_or_insert_with(Capabilities::detect)
+}
+
+/// Override the detected capabilities of the terminal, e.g. from a configuration option.
+///
+/// This is needed to use the features of a terminal that is not `stdout`, see `Capabilities::detect`.
+pub fn set_capabilities(caps: Capabilities) {
+    *CAPABILITIES.lock().unwrap_or_else(|err| err.into_inner()) = Some(caps);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Compile a terminfo entry with the given names, number of booleans and numbers, without any strings.
+    fn entry(magic: i16, names: &[u8], bools: usize, numbers: &[i32]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for &n in &[
+            magic,
+            names.len() as i16,
+            bools as i16,
+            numbers.len() as i16,
+            0,
+            0,
+        ] {
+            data.extend_from_slice(&n.to_le_bytes());
+        }
+        data.extend_from_slice(names);
+        data.resize(data.len() + bools, 1);
+        if data.len() % 2 == 1 {
+            data.push(0);
+        }
+        for &n in numbers {
+            match magic {
+                0o432 => data.extend_from_slice(&(n as i16).to_le_bytes()),
+                _ => data.extend_from_slice(&n.to_le_bytes()),
+            }
+        }
+        data
+    }
+
+    fn numbers(colors: i32) -> Vec<i32> {
+        vec![
+            80, 24, 8, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, colors, 64,
+        ]
+    }
+
+    #[test]
+    fn legacy_format() {
+        let data = entry(
+            0o432,
+            b"xterm-256color|xterm with 256 colors\0",
+            38,
+            &numbers(256),
+        );
+        assert_eq!(parse_terminfo_colors(&data), Some(256));
+    }
+
+    #[test]
+    fn numbers_are_aligned() {
+        let data = entry(0o432, b"vt100\0", 2, &numbers(8));
+        assert_eq!(parse_terminfo_colors(&data), Some(8));
+        let data = entry(0o432, b"vt10\0", 2, &numbers(8));
+        assert_eq!(parse_terminfo_colors(&data), Some(8));
+    }
+
+    #[test]
+    fn extended_format() {
+        let data = entry(0o1036, b"xterm-direct\0", 38, &numbers(1 << 24));
+        assert_eq!(parse_terminfo_colors(&data), Some(1 << 24));
+    }
+
+    #[test]
+    fn missing_colors() {
+        let data = entry(0o432, b"vt100\0", 38, &numbers(-1));
+        assert_eq!(parse_terminfo_colors(&data), Some(0));
+        let data = entry(0o432, b"vt100\0", 38, &[80, 24]);
+        assert_eq!(parse_terminfo_colors(&data), Some(0));
+    }
+
+    #[test]
+    fn invalid_entries() {
+        assert_eq!(parse_terminfo_colors(b""), None);
+        let data = entry(0o433, b"vt100\0", 38, &numbers(8));
+        assert_eq!(parse_terminfo_colors(&data), None);
+        let data = entry(0o432, b"xterm-256color\0", 38, &numbers(256));
+        assert_eq!(parse_terminfo_colors(&data[..data.len() - 4]), None);
+    }
+}
@@ -0,0 +1,153 @@
+//! Access to the clipboard through the terminal (OSC 52).
+
+use std::env;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// The clipboard that is accessed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Selection {
+    /// The clipboard that is used with `Ctrl+C` / `Ctrl+V`.
+    Clipboard,
+    /// The primary selection on X11 and Wayland, i.e. the selected text that is pasted with the middle mouse button.
+    Primary,
+}
+
+impl Selection {
+    fn param(self) -> char {
+        match self {
+            Selection::Clipboard => 'c',
+            Selection::Primary => 'p',
+        }
+    }
+}
+
+/// A type that when `Display`ed, copies the text to the specified clipboard.
+///
+/// This works wherever the terminal is, e.g. over SSH, as long as the terminal allows it.
+/// Inside of tmux (i.e. if `TMUX` is set), the sequence is passed through to the outer terminal,
+/// which requires `allow-passthrough` to be enabled in tmux.
+#[derive(Clone, Copy)]
+pub struct CopyToClipboard<'a>(pub &'a str, pub Selection);
+
+impl<'a> Display for CopyToClipboard<'a> {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt_osc52(fmt, self.1, &encode(self.0.as_bytes()))
+    }
+}
+
+#[cfg(unix)]
+/// Requests the contents of the specified clipboard, see `Terminal::read_clipboard`.
+pub struct RequestClipboard(pub Selection);
+
+#[cfg(unix)]
+impl Display for RequestClipboard {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt_osc52(fmt, self.0, "?")
+    }
+}
+
+fn fmt_osc52(fmt: &mut Formatter, selection: Selection, data: &str) -> FmtResult {
+    let seq = format!("\x1B]52;{};{}\x1B\\", selection.param(), data);
+    if env::var_os("TMUX").is_some() {
+        // tmux passes the contents of `DCS tmux; ... ST` on, with every `ESC` in them doubled
+        write!(fmt, "\x1BPtmux;{}\x1B\\", seq.replace('\x1B', "\x1B\x1B"))
+    } else {
+        fmt.write_str(&seq)
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode `data` as base64, with padding.
+pub fn encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        // A chunk of n bytes is encoded with n + 1 characters, the rest is padding
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(unix)]
+/// Decode base64 `data`, with or without padding. Returns `None` if `data` is not valid base64.
+pub fn decode(data: &[u8]) -> Option<Vec<u8>> {
+    let end = data.iter().rposition(|&b| b != b'=').map_or(0, |i| i + 1);
+    let mut out = Vec::with_capacity(end * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for &b in &data[..end] {
+        acc = acc << 6 | BASE64.iter().position(|&c| c == b)? as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The test vectors of RFC 4648
+    const VECTORS: [(&str, &str); 7] = [
+        ("", ""),
+        ("f", "Zg=="),
+        ("fo", "Zm8="),
+        ("foo", "Zm9v"),
+        ("foob", "Zm9vYg=="),
+        ("fooba", "Zm9vYmE="),
+        ("foobar", "Zm9vYmFy"),
+    ];
+
+    #[test]
+    fn encode_vectors() {
+        for &(data, encoded) in &VECTORS {
+            assert_eq!(encode(data.as_bytes()), encoded);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn decode_vectors() {
+        for &(data, encoded) in &VECTORS {
+            assert_eq!(decode(encoded.as_bytes()).unwrap(), data.as_bytes());
+            // Padding is optional
+            let unpadded = encoded.trim_end_matches('=');
+            assert_eq!(decode(unpadded.as_bytes()).unwrap(), data.as_bytes());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn round_trip() {
+        let data: Vec<u8> = (0..=255).collect();
+        for len in 0..data.len() {
+            assert_eq!(
+                decode(encode(&data[..len]).as_bytes()).unwrap(),
+                &data[..len]
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn decode_rejects_invalid_characters() {
+        assert_eq!(decode(b"Zm9v!"), None);
+        assert_eq!(decode(b"Zm 9v"), None);
+        assert_eq!(decode(b"Zm=9v"), None);
+    }
+}
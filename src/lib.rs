@@ -11,24 +11,57 @@
 //! Throughout this crate X and Y are used to denote the coordinates of the cursor.
 //! X corresponds to columns and Y corresponds to rows.
 //! All tuples like `(i32, i32)` are interpreted as `(X, Y)`.
+//! Coordinates are zero-based on every platform, i.e. the top left cell of the terminal is at `(0, 0)` (see `Pos`).
 //!
 //! # API
 //! This crate provides 2 APIs that can be used to achieve the same effects:
 //!
-//! - A functions based approach that provides very simple functions to directly interact with the terminal (see the functions `set_pos`, `get_pos` and `clear`).
+//! - A functions based approach that provides very simple functions to directly interact with the terminal (see the functions `set_pos`, `get_pos`, `size` and `clear`).
 //! - A newtype pattern based approach that provies a bunch of types which all implement `std::fmt::Display` (see the structs section below).
-//!   These types call the `set_pos`, `get_pos` and `clear` functions internally, when they get formatted.
+//!   On *NIX, these types write their ANSI escape sequences into the formatter, so they compose with `write!` to files, `String`s and buffered writers.
+//!   On Windows, they call the `set_pos`, `get_pos` and `clear` functions internally, when they get formatted.
+//!   Wrap a type in `Direct` to opt into performing it on the terminal immediately, on every platform.
+//!
+//! To operate on something other than the process' terminal (a `/dev/tty` handle, a pty master, a socket, a `Vec<u8>`, ...),
+//! wrap the reader and writer in a `Terminal`. It offers the same operations as methods,
+//! and every struct of this crate implements `Command`, so it can be applied to a `Terminal` with `Terminal::execute`.
+//! A `TrackedTerminal` additionally keeps track of the cursor position, so it does not have to be queried from the terminal.
 //!
 //! # Watch out!
-//! - Both APIs **always** operate on the "default" terminal that is bound to the process.
-//!   In other words, on Windows `GetStdHandle(STD_OUTPUT_HANDLE)` is used, and on *NIX, the ANSI terminal communcation is done through `stdout` / `stdin`.
-//! - Drawing outside the boundaries of the buffer / terminal is **undefined behaviour**.
+//! - Both of the above APIs **always** operate on the "default" terminal that is bound to the process.
+//!   In other words, on Windows `GetStdHandle(STD_OUTPUT_HANDLE)` is used, and on *NIX, the ANSI terminal communcation is done through `stdout`.
+//!   Queries like `get_pos` are sent to the controlling terminal `/dev/tty` on *NIX, so they work when `stdin` / `stdout` are redirected.
+//!   Only if there is no controlling terminal, `stdin` / `stdout` are used instead.
+//! - Types without an equivalent in the Windows console API (like `CursorStyle` and `Style`) write ANSI escape sequences on every platform.
+//!   On Windows, these only have an effect on consoles with virtual terminal processing enabled (Windows 10 and newer).
+//! - Colors and other features, that not every terminal supports, are degraded according to the detected `Capabilities`.
+//!   Colors are left out entirely if `stdout` is not a terminal, or `NO_COLOR` is set. Use `set_capabilities` to override this.
+//!   The detection only looks at `stdout`, so output to another terminal (like `Terminal::tty`) needs `set_capabilities` as well.
+//! - Drawing outside the boundaries of the buffer / terminal is **undefined behaviour**. Use `size` to stay within bounds.
 
-use std::fmt::{Display, Formatter, Result as FmtResult, Error as FmtError};
+use std::fmt::{Display, Formatter, Result as FmtResult, Error as FmtError, Write as FmtWrite};
 
-use std::io;
+use std::io::{self, Write};
+use std::time::Duration;
 
+mod ansi;
+mod capabilities;
+mod clipboard;
+mod palette;
 mod platform;
+mod reply;
+mod style;
+mod terminal;
+mod tracked;
+
+pub use capabilities::{capabilities, set_capabilities, Capabilities, ColorSupport};
+pub use clipboard::{CopyToClipboard, Selection};
+pub use palette::{PaletteGuard, ResetPalette, ResetPaletteColor, SetPaletteColor};
+#[cfg(not(target_os = "windows"))]
+pub use platform::RawModeGuard;
+pub use style::{Color, Reset, Style, Theme, Underline};
+pub use terminal::{Command, Terminal, WindowSize};
+pub use tracked::{CursorState, TrackedTerminal};
 
 /// The error generated by operations on the terminal.
 #[derive(Debug)]
@@ -37,6 +70,17 @@ pub enum Error {
     Io(io::Error),
     /// An opaque, platform-implementation-specific error.
     PlatformSpecific,
+    /// The cursor was to be moved outside of the terminal.
+    OutOfBounds {
+        /// The position that is out of bounds.
+        pos: Pos,
+        /// The (width, height) of the terminal.
+        size: (i32, i32),
+    },
+    /// The terminal did not reply to a query in time.
+    Timeout,
+    /// An argument is outside of the range the operation accepts, e.g. a negative coordinate.
+    InvalidArgument,
 }
 
 impl From<io::Error> for Error {
@@ -51,31 +95,88 @@ impl From<Error> for FmtError {
     }
 }
 
+/// A position on the terminal screen.
+///
+/// The coordinates are zero-based, so the top left cell of the terminal is `Pos { x: 0, y: 0 }`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Pos {
+    /// The column.
+    pub x: i32,
+    /// The row.
+    pub y: i32,
+}
+
+impl Pos {
+    /// Create a new position from the column `x` and the row `y`.
+    pub fn new(x: i32, y: i32) -> Self {
+        Pos { x, y }
+    }
+
+    /// Check whether this position lies within a terminal of the given (width, height).
+    pub fn is_within(self, (width, height): (i32, i32)) -> bool {
+        self.x >= 0 && self.x < width && self.y >= 0 && self.y < height
+    }
+
+    /// Saturate this position to the last column / row of a terminal of the given (width, height).
+    pub fn clamp_to(self, (width, height): (i32, i32)) -> Self {
+        Pos {
+            x: self.x.max(0).min(width - 1),
+            y: self.y.max(0).min(height - 1),
+        }
+    }
+
+    // Return this position if it lies within `size`, otherwise an `Error::OutOfBounds`.
+    fn check(self, size: (i32, i32)) -> Result<Self, Error> {
+        if self.is_within(size) {
+            Ok(self)
+        } else {
+            Err(Error::OutOfBounds { pos: self, size })
+        }
+    }
+}
+
+impl From<(i32, i32)> for Pos {
+    fn from((x, y): (i32, i32)) -> Self {
+        Pos { x, y }
+    }
+}
+
+impl From<Pos> for (i32, i32) {
+    fn from(pos: Pos) -> Self {
+        (pos.x, pos.y)
+    }
+}
+
+// Negative coordinates would turn into garbage escape sequences, so they are clamped to 0.
+fn fmt_cursor_pos(fmt: &mut Formatter, x: i32, y: i32) -> FmtResult {
+    platform::fmt_cursor_pos(fmt, x.max(0), y.max(0))
+}
+
 /// A type that when `Display`ed, moves the cursor to the specified coordinates.
+///
+/// Negative coordinates are treated as 0. Use `set_pos` or `Terminal::execute` to get an error instead.
 #[derive(Clone, Copy)]
 pub struct Goto(pub i32, pub i32);
 
 impl Display for Goto {
-    fn fmt(&self, _fmt: &mut Formatter) -> FmtResult {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
         let Goto(x, y) = *self;
-        platform::set_cursor_pos(x, y)?;
-        Ok(())
+        fmt_cursor_pos(fmt, x, y)
     }
 }
 
 /// A type that when `Display`ed, moves the cursor by the specified amounts.
 ///
 /// This moves the cursor to the specified coordinates, relative to it's previous position.
+/// The cursor stops at the edges of the screen.
+/// See `move_by_checked` for a move that fails instead.
 #[derive(Clone, Copy)]
 pub struct Relative(pub i32, pub i32);
 
 impl Display for Relative {
-    fn fmt(&self, _fmt: &mut Formatter) -> FmtResult {
-        let (cur_x, cur_y) = platform::get_cursor_pos()?;
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
         let Relative(x, y) = *self;
-        let (x, y) = (x + cur_x, y + cur_y);
-        platform::set_cursor_pos(x, y)?;
-        Ok(())
+        platform::fmt_relative(fmt, x, y)
     }
 }
 
@@ -87,7 +188,7 @@ pub struct Left(pub i32);
 
 impl Display for Left {
     fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
-        Relative(-self.0, 0).fmt(fmt)
+        Relative(self.0.saturating_neg(), 0).fmt(fmt)
     }
 }
 
@@ -111,7 +212,7 @@ pub struct Up(pub i32);
 
 impl Display for Up {
     fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
-        Relative(0, -self.0).fmt(fmt)
+        Relative(0, self.0.saturating_neg()).fmt(fmt)
     }
 }
 
@@ -127,32 +228,970 @@ impl Display for Down {
     }
 }
 
+/// A type that when `Display`ed, moves the cursor to the specified column, staying on the same row.
+///
+/// A negative column is treated as 0. Use `Terminal::execute` to get an error instead.
+#[derive(Clone, Copy)]
+pub struct Column(pub i32);
+
+impl Display for Column {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        platform::fmt_column(fmt, self.0.max(0))
+    }
+}
+
+/// A type that when `Display`ed, moves the cursor to the specified row, staying in the same column.
+///
+/// A negative row is treated as 0. Use `Terminal::execute` to get an error instead.
+#[derive(Clone, Copy)]
+pub struct Row(pub i32);
+
+impl Display for Row {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        platform::fmt_row(fmt, self.0.max(0))
+    }
+}
+
+/// A type that when `Display`ed, moves the cursor to the start of the line the specified amount of lines down.
+///
+/// The cursor stops at the bottom of the screen.
+#[derive(Clone, Copy)]
+pub struct NextLine(pub i32);
+
+impl Display for NextLine {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        platform::fmt_next_line(fmt, self.0)
+    }
+}
+
+/// A type that when `Display`ed, moves the cursor to the start of the line the specified amount of lines up.
+///
+/// The cursor stops at the top of the screen. This is identical to a `NextLine(-val)`.
+#[derive(Clone, Copy)]
+pub struct PrevLine(pub i32);
+
+impl Display for PrevLine {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        NextLine(self.0.saturating_neg()).fmt(fmt)
+    }
+}
+
 /// A type that when `Display`ed, clears the entire terminal screen.
 ///
-/// In effect, this sets every terminal cell to a space `' '`.
+/// In effect, this sets every terminal cell to a space `' '`. The cursor position is not changed.
+/// This is identical to `ClearMode::All`.
 #[derive(Clone, Copy)]
 pub struct Clear;
 
 impl Display for Clear {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        platform::fmt_clear_with(fmt, ClearMode::All)
+    }
+}
+
+/// The part of the terminal to clear.
+///
+/// When `Display`ed, the selected cells are set to a space `' '`. The cursor position is not changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ClearMode {
+    /// The entire screen.
+    All,
+    /// The cursor cell and everything after it, up to the end of the screen.
+    Below,
+    /// Everything from the start of the screen, up to and including the cursor cell.
+    Above,
+    /// The lines that were scrolled out of the screen. The screen itself is not cleared.
+    Scrollback,
+    /// The entire line the cursor is on.
+    Line,
+    /// The line the cursor is on, from its start up to and including the cursor cell.
+    LineLeft,
+    /// The line the cursor is on, from the cursor cell up to its end.
+    LineRight,
+}
+
+impl Display for ClearMode {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        platform::fmt_clear_with(fmt, *self)
+    }
+}
+
+/// A type that when `Display`ed, saves the current cursor position.
+///
+/// The position can be restored later with `RestorePos`.
+/// There is only a single slot for the saved position, so saving again overwrites the previously saved position.
+/// On *NIX, the attributes of the text (see `Style`) are saved as well and restored by `RestorePos`.
+#[derive(Clone, Copy)]
+pub struct SavePos;
+
+impl Display for SavePos {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        platform::fmt_save_pos(fmt)
+    }
+}
+
+/// A type that when `Display`ed, moves the cursor back to the position saved by `SavePos`.
+#[derive(Clone, Copy)]
+pub struct RestorePos;
+
+impl Display for RestorePos {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        platform::fmt_restore_pos(fmt)
+    }
+}
+
+/// A type that when `Display`ed, hides the cursor.
+///
+/// This avoids a flickering cursor while redrawing. See `HiddenCursor` for a guard that shows the cursor again.
+#[derive(Clone, Copy)]
+pub struct Hide;
+
+impl Display for Hide {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        platform::fmt_hide(fmt)
+    }
+}
+
+/// A type that when `Display`ed, shows the cursor again after it has been hidden with `Hide`.
+#[derive(Clone, Copy)]
+pub struct Show;
+
+impl Display for Show {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        platform::fmt_show(fmt)
+    }
+}
+
+/// A guard that hides the cursor and shows it again when dropped.
+pub struct HiddenCursor(());
+
+impl HiddenCursor {
+    /// Hide the cursor until the returned guard is dropped.
+    pub fn new() -> Result<Self, Error> {
+        hide()?;
+        Ok(HiddenCursor(()))
+    }
+}
+
+impl Drop for HiddenCursor {
+    fn drop(&mut self) {
+        let _ = show();
+        let _ = io::stdout().flush();
+    }
+}
+
+/// The shape of the cursor, and whether it blinks.
+///
+/// When `Display`ed, the cursor changes to this style.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CursorStyle {
+    /// The default style of the terminal, usually configured by the user.
+    Default = 0,
+    /// A blinking block.
+    BlinkingBlock = 1,
+    /// A steady block.
+    SteadyBlock = 2,
+    /// A blinking underline.
+    BlinkingUnderline = 3,
+    /// A steady underline.
+    SteadyUnderline = 4,
+    /// A blinking vertical bar.
+    BlinkingBar = 5,
+    /// A steady vertical bar.
+    SteadyBar = 6,
+}
+
+impl Display for CursorStyle {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        // The discriminants are the parameters of DECSCUSR
+        write!(fmt, "\x1B[{} q", *self as u8)
+    }
+}
+
+/// A type that when `Display`ed, sets the title of the terminal window.
+///
+/// Control characters are left out of the title, so titles from untrusted sources can not inject escape sequences.
+/// Use `PushTitle` and `PopTitle` to bring back the original title afterwards.
+#[derive(Clone, Copy)]
+pub struct SetTitle<'a>(pub &'a str);
+
+impl<'a> Display for SetTitle<'a> {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        platform::fmt_title(fmt, self.0)
+    }
+}
+
+/// A type that when `Display`ed, sets the icon name of the terminal window, i.e. the title of its minimized form or tab.
+///
+/// Control characters are left out of the name, like for `SetTitle`.
+/// The Windows console has no icon name, so this has no effect there.
+#[derive(Clone, Copy)]
+pub struct SetIconName<'a>(pub &'a str);
+
+impl<'a> Display for SetIconName<'a> {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        platform::fmt_icon_name(fmt, self.0)
+    }
+}
+
+/// A type that when `Display`ed, sets both the title and the icon name of the terminal window.
+///
+/// Control characters are left out of the title, like for `SetTitle`.
+#[derive(Clone, Copy)]
+pub struct SetTitleAndIconName<'a>(pub &'a str);
+
+impl<'a> Display for SetTitleAndIconName<'a> {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        platform::fmt_title_and_icon_name(fmt, self.0)
+    }
+}
+
+/// A type that when `Display`ed, saves the title and the icon name of the terminal window on a stack.
+///
+/// Not all terminals support this, it has no effect on those.
+/// On Windows, the title of the console is saved on a stack kept by this crate instead.
+#[derive(Clone, Copy)]
+pub struct PushTitle;
+
+impl Display for PushTitle {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        platform::fmt_push_title(fmt)
+    }
+}
+
+/// A type that when `Display`ed, restores the title and the icon name saved with `PushTitle`.
+#[derive(Clone, Copy)]
+pub struct PopTitle;
+
+impl Display for PopTitle {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        platform::fmt_pop_title(fmt)
+    }
+}
+
+/// A type that when `Display`ed, prints `text` as a link to `url`.
+///
+/// Links with the same `id` and `url` are treated as one link by the terminal, e.g. when they are hovered,
+/// so a link split into multiple fragments (like a URL wrapped over multiple lines drawn with `Goto`) should use one `id`.
+/// Control characters are left out of the `url` and `id`.
+///
+/// If the terminal is not known to support hyperlinks (see `Capabilities`), only `text` is printed.
+/// This includes the case where `stdout` is not a terminal, even if the link is written to one, see `Capabilities::detect`.
+#[derive(Clone, Copy)]
+pub struct Hyperlink<'a> {
+    /// The target of the link.
+    pub url: &'a str,
+    /// An identifier for the fragments of a link.
+    pub id: Option<&'a str>,
+    /// The text shown for the link.
+    pub text: &'a str,
+}
+
+impl<'a> Hyperlink<'a> {
+    /// Create a link to `url` without an `id`.
+    pub fn new(url: &'a str, text: &'a str) -> Self {
+        Hyperlink {
+            url,
+            id: None,
+            text,
+        }
+    }
+}
+
+impl<'a> Display for Hyperlink<'a> {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        if !capabilities().hyperlinks {
+            return fmt.write_str(self.text);
+        }
+        fmt.write_str("\x1B]8;")?;
+        if let Some(id) = self.id {
+            fmt.write_str("id=")?;
+            // `:` and `;` separate the parameters
+            for c in id
+                .chars()
+                .filter(|&c| !c.is_control() && c != ':' && c != ';')
+            {
+                fmt.write_char(c)?;
+            }
+        }
+        fmt.write_char(';')?;
+        for c in self.url.chars().filter(|c| !c.is_control()) {
+            fmt.write_char(c)?;
+        }
+        write!(fmt, "\x1B\\{}\x1B]8;;\x1B\\", self.text)
+    }
+}
+
+/// A type that when `Display`ed, switches to the alternate screen buffer.
+///
+/// The alternate screen has no scrollback and is discarded by `LeaveAlternateScreen`,
+/// which brings back the original contents of the terminal untouched.
+/// The cursor position is saved on entering and restored on leaving.
+/// See `AlternateScreen` for a guard that leaves the alternate screen again.
+#[derive(Clone, Copy)]
+pub struct EnterAlternateScreen;
+
+impl Display for EnterAlternateScreen {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt.write_str("\x1B[?1049h")
+    }
+}
+
+/// A type that when `Display`ed, switches back from the alternate screen buffer to the normal one.
+#[derive(Clone, Copy)]
+pub struct LeaveAlternateScreen;
+
+impl Display for LeaveAlternateScreen {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt.write_str("\x1B[?1049l")
+    }
+}
+
+/// A guard that switches to the alternate screen buffer and back to the normal one when dropped.
+///
+/// Full-screen programs can draw on the alternate screen,
+/// and the user gets back the contents of their terminal untouched after the program exits.
+pub struct AlternateScreen(());
+
+impl AlternateScreen {
+    /// Switch to the alternate screen until the returned guard is dropped.
+    pub fn enter() -> Result<Self, Error> {
+        enter_alternate_screen()?;
+        Ok(AlternateScreen(()))
+    }
+}
+
+impl Drop for AlternateScreen {
+    fn drop(&mut self) {
+        let _ = leave_alternate_screen();
+        let _ = io::stdout().flush();
+    }
+}
+
+/// A type that when `Display`ed, confines scrolling to the rows from the first to the second specified row (inclusive).
+///
+/// Lines scrolled out of the region are lost and the rows outside of it, like a header or a status bar, stay in place.
+/// This also moves the cursor to the top left corner of the screen.
+/// Nothing happens if the rows are negative or if the first row is not above the second one.
+/// Use `set_scroll_region` or `Terminal::execute` to get an error instead.
+#[derive(Clone, Copy)]
+pub struct ScrollRegion(pub i32, pub i32);
+
+impl Display for ScrollRegion {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        let ScrollRegion(top, bottom) = *self;
+        // An invalid region would be ignored by some terminals and reset the region of others
+        if top < 0 || bottom <= top {
+            return Ok(());
+        }
+        // DECSTBM is one-based
+        write!(
+            fmt,
+            "\x1B[{};{}r",
+            top.saturating_add(1),
+            bottom.saturating_add(1)
+        )
+    }
+}
+
+/// A type that when `Display`ed, makes the whole screen scroll again, after a `ScrollRegion`.
+///
+/// This also moves the cursor to the top left corner of the screen.
+#[derive(Clone, Copy)]
+pub struct ResetScrollRegion;
+
+impl Display for ResetScrollRegion {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt.write_str("\x1B[r")
+    }
+}
+
+/// A type that when `Display`ed, scrolls the contents of the scroll region up by the specified amount of lines.
+///
+/// New blank lines appear at the bottom. The cursor does not move.
+#[derive(Clone, Copy)]
+pub struct ScrollUp(pub i32);
+
+impl Display for ScrollUp {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        // A parameter of 0 means 1, so nothing must be written for no scrolling at all
+        match self.0 {
+            n if n > 0 => write!(fmt, "\x1B[{}S", n),
+            n if n < 0 => write!(fmt, "\x1B[{}T", n.unsigned_abs()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A type that when `Display`ed, scrolls the contents of the scroll region down by the specified amount of lines.
+///
+/// New blank lines appear at the top. The cursor does not move. This is identical to a `ScrollUp(-val)`.
+#[derive(Clone, Copy)]
+pub struct ScrollDown(pub i32);
+
+impl Display for ScrollDown {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        ScrollUp(self.0.saturating_neg()).fmt(fmt)
+    }
+}
+
+/// A type that when `Display`ed, moves the cursor down one line, scrolling the scroll region up if it is at the bottom of it.
+#[derive(Clone, Copy)]
+pub struct Index;
+
+impl Display for Index {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt.write_str("\x1BD")
+    }
+}
+
+/// A type that when `Display`ed, moves the cursor up one line, scrolling the scroll region down if it is at the top of it.
+#[derive(Clone, Copy)]
+pub struct ReverseIndex;
+
+impl Display for ReverseIndex {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt.write_str("\x1BM")
+    }
+}
+
+// Write a control sequence, that takes a count as its parameter, with the final byte `op`.
+// A count of 0 means 1 to the terminal, so nothing is written for 0 or negative counts.
+fn fmt_count(fmt: &mut Formatter, n: i32, op: char) -> FmtResult {
+    if n > 0 {
+        write!(fmt, "\x1B[{}{}", n, op)
+    } else {
+        Ok(())
+    }
+}
+
+/// A type that when `Display`ed, inserts the specified amount of blank lines at the cursor.
+///
+/// The line the cursor is on and the lines below it are shifted down, lines shifted out of the scroll region are lost.
+/// The cursor moves to the start of the line. Nothing happens if the amount is negative.
+/// Use `insert_lines` or `Terminal::execute` to get an error instead.
+#[derive(Clone, Copy)]
+pub struct InsertLines(pub i32);
+
+impl Display for InsertLines {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt_count(fmt, self.0, 'L')
+    }
+}
+
+/// A type that when `Display`ed, deletes the specified amount of lines, starting with the line the cursor is on.
+///
+/// The lines below are shifted up and blank lines appear at the bottom of the scroll region.
+/// The cursor moves to the start of the line. Nothing happens if the amount is negative.
+/// Use `delete_lines` or `Terminal::execute` to get an error instead.
+#[derive(Clone, Copy)]
+pub struct DeleteLines(pub i32);
+
+impl Display for DeleteLines {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt_count(fmt, self.0, 'M')
+    }
+}
+
+/// A type that when `Display`ed, inserts the specified amount of blank characters at the cursor.
+///
+/// The character the cursor is on and the ones right of it are shifted right, characters shifted out of the line are lost.
+/// The cursor does not move. Nothing happens if the amount is negative.
+/// Use `insert_chars` or `Terminal::execute` to get an error instead.
+#[derive(Clone, Copy)]
+pub struct InsertChars(pub i32);
+
+impl Display for InsertChars {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt_count(fmt, self.0, '@')
+    }
+}
+
+/// A type that when `Display`ed, deletes the specified amount of characters, starting with the one the cursor is on.
+///
+/// The characters right of them are shifted left and blank characters appear at the end of the line.
+/// The cursor does not move. Nothing happens if the amount is negative.
+/// Use `delete_chars` or `Terminal::execute` to get an error instead.
+#[derive(Clone, Copy)]
+pub struct DeleteChars(pub i32);
+
+impl Display for DeleteChars {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt_count(fmt, self.0, 'P')
+    }
+}
+
+/// A type that when `Display`ed, sets the specified amount of characters to a space `' '`,
+/// starting with the one the cursor is on.
+///
+/// Unlike `DeleteChars`, nothing is shifted. The cursor does not move. Nothing happens if the amount is negative.
+/// Use `erase_chars` or `Terminal::execute` to get an error instead.
+#[derive(Clone, Copy)]
+pub struct EraseChars(pub i32);
+
+impl Display for EraseChars {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt_count(fmt, self.0, 'X')
+    }
+}
+
+/// A type that when `Display`ed, performs the wrapped type directly on the default terminal.
+///
+/// Nothing is written into the formatter. Instead, the wrapped type is written to `stdout`, which is then flushed.
+/// This is how all types of this crate behaved in earlier versions.
+#[derive(Clone, Copy)]
+pub struct Direct<T>(pub T);
+
+impl<T: Display> Display for Direct<T> {
     fn fmt(&self, _fmt: &mut Formatter) -> FmtResult {
-        platform::clear()?;
+        let mut stdout = io::stdout();
+        write!(stdout, "{}", self.0).map_err(Error::from)?;
+        stdout.flush().map_err(Error::from)?;
         Ok(())
     }
 }
 
+// Perform `cmd` on the default terminal.
+fn perform<C: Display>(cmd: C) -> Result<(), Error> {
+    write!(io::stdout(), "{}", cmd)?;
+    Ok(())
+}
+
+// Perform `cmd` on the default terminal, with the checks of its `Command` implementation.
+fn execute<C: Command>(cmd: C) -> Result<(), Error> {
+    Terminal::stdio().execute(&cmd)
+}
+
 /// Set the cursor position to the specified coordinates.
+///
+/// Negative coordinates are rejected with an `Error::InvalidArgument`.
 pub fn set_pos(x: i32, y: i32) -> Result<(), Error> {
+    if x < 0 || y < 0 {
+        return Err(Error::InvalidArgument);
+    }
     platform::set_cursor_pos(x, y)
 }
 
+/// Set the cursor position to the specified coordinates, if they lie within the terminal.
+///
+/// Otherwise, the cursor is not moved and an `Error::OutOfBounds` is returned.
+pub fn set_pos_checked(x: i32, y: i32) -> Result<(), Error> {
+    let pos = Pos::new(x, y).check(size()?)?;
+    platform::set_cursor_pos(pos.x, pos.y)
+}
+
+/// Set the cursor position to the specified coordinates, saturated to the last column / row of the terminal.
+///
+/// The position the cursor was moved to is returned.
+pub fn set_pos_clamped(x: i32, y: i32) -> Result<Pos, Error> {
+    let pos = Pos::new(x, y).clamp_to(size()?);
+    platform::set_cursor_pos(pos.x, pos.y)?;
+    Ok(pos)
+}
+
+/// Move the cursor by the specified amounts, if it ends up within the terminal.
+///
+/// Otherwise, the cursor is not moved and an `Error::OutOfBounds` is returned.
+/// This is the checked version of `Relative`.
+pub fn move_by_checked(x: i32, y: i32) -> Result<(), Error> {
+    let cur = get_pos()?;
+    set_pos_checked(cur.x.saturating_add(x), cur.y.saturating_add(y))
+}
+
+/// Move the cursor by the specified amounts, saturated to the borders of the terminal.
+///
+/// The position the cursor was moved to is returned.
+/// This is the clamping version of `Relative`.
+pub fn move_by_clamped(x: i32, y: i32) -> Result<Pos, Error> {
+    let cur = get_pos()?;
+    set_pos_clamped(cur.x.saturating_add(x), cur.y.saturating_add(y))
+}
+
 /// Get the current cursor position.
-/// 
-/// The tuple returned contains the (x, y) coordinates of the cursor position.
-pub fn get_pos() -> Result<(i32, i32), Error> {
+///
+/// On *NIX, this blocks until the terminal replies. See `get_pos_timeout` for a version that gives up.
+pub fn get_pos() -> Result<Pos, Error> {
     platform::get_cursor_pos()
 }
 
+/// Get the current cursor position, giving up if the terminal does not reply within `timeout`.
+///
+/// If the reply does not arrive in time, an `Error::Timeout` is returned.
+/// A reply that arrives later is dropped, so it is not taken for the reply to the next query.
+/// On Windows, the position is always available immediately.
+pub fn get_pos_timeout(timeout: Duration) -> Result<Pos, Error> {
+    platform::get_cursor_pos_timeout(timeout)
+}
+
+/// Take the input that was read while waiting for the reply to a query, like `get_pos`, but did not belong to it.
+///
+/// Keys pressed by the user while a query is in progress end up here, instead of being lost.
+/// On Windows, this is always empty.
+pub fn take_pending_input() -> Vec<u8> {
+    platform::take_pending_input()
+}
+
+/// Save the current cursor position, so it can be restored later with `restore_pos`.
+///
+/// Unlike remembering the result of `get_pos`, this does not need a round-trip to the terminal.
+pub fn save_pos() -> Result<(), Error> {
+    perform(SavePos)
+}
+
+/// Move the cursor back to the position saved by `save_pos`.
+pub fn restore_pos() -> Result<(), Error> {
+    perform(RestorePos)
+}
+
+/// Run `f` and move the cursor back to where it was before afterwards, even if `f` panics.
+///
+/// This uses `save_pos` and `restore_pos`, so it must not be nested.
+/// On *NIX, these save and restore the attributes of the text as well, so a `Style` set inside of `f` is undone too.
+pub fn with_saved_pos<T, F: FnOnce() -> T>(f: F) -> Result<T, Error> {
+    save_pos()?;
+    let guard = SavedPosGuard(());
+    let res = f();
+    ::std::mem::forget(guard);
+    restore_pos()?;
+    Ok(res)
+}
+
+// Moves the cursor back to the saved position when dropped, in case `with_saved_pos` is left by a panic.
+struct SavedPosGuard(());
+
+impl Drop for SavedPosGuard {
+    fn drop(&mut self) {
+        let _ = restore_pos();
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Hide the cursor.
+pub fn hide() -> Result<(), Error> {
+    perform(Hide)
+}
+
+/// Show the cursor again after it has been hidden with `hide`.
+pub fn show() -> Result<(), Error> {
+    perform(Show)
+}
+
+/// Set the shape of the cursor, and whether it blinks.
+///
+/// Use `CursorStyle::Default` to restore the style the terminal had initially, e.g. before the program exits.
+pub fn set_style(style: CursorStyle) -> Result<(), Error> {
+    perform(style)
+}
+
+/// Apply the colors and attributes of `style` to all text printed afterwards.
+pub fn set_text_style(style: Style) -> Result<(), Error> {
+    perform(style)
+}
+
+/// Reset all colors and attributes of the text to the default.
+pub fn reset_text_style() -> Result<(), Error> {
+    perform(Reset)
+}
+
+/// Get the color of the text, giving up if the terminal does not reply within `timeout`.
+///
+/// The color is always a `Color::Rgb`. If the terminal does not support the query, an `Error::Timeout` is returned.
+/// Not supported on Windows.
+pub fn query_fg_color(timeout: Duration) -> Result<Color, Error> {
+    platform::query_fg_color(timeout)
+}
+
+/// Get the color of the background, giving up if the terminal does not reply within `timeout`.
+///
+/// See `query_fg_color`.
+pub fn query_bg_color(timeout: Duration) -> Result<Color, Error> {
+    platform::query_bg_color(timeout)
+}
+
+/// Get the color of the cursor, giving up if the terminal does not reply within `timeout`.
+///
+/// See `query_fg_color`.
+pub fn query_cursor_color(timeout: Duration) -> Result<Color, Error> {
+    platform::query_cursor_color(timeout)
+}
+
+/// Get the color of the entry of the 256 color palette at `index`, giving up if the terminal does not reply within `timeout`.
+///
+/// See `query_fg_color`.
+pub fn query_palette_color(index: u8, timeout: Duration) -> Result<Color, Error> {
+    platform::query_palette_color(index, timeout)
+}
+
+/// Change the entry of the 256 color palette at `index` to `color`.
+///
+/// See `SetPaletteColor` for how `color` is applied, and `PaletteGuard` for a guard that restores the original color.
+pub fn set_palette_color(index: u8, color: Color) -> Result<(), Error> {
+    perform(SetPaletteColor(index, color))
+}
+
+/// Reset the entry of the palette at `index` to the color configured by the user.
+pub fn reset_palette_color(index: u8) -> Result<(), Error> {
+    perform(ResetPaletteColor(index))
+}
+
+/// Reset all entries of the palette to the colors configured by the user.
+pub fn reset_palette() -> Result<(), Error> {
+    perform(ResetPalette)
+}
+
+/// Determine whether the terminal has a dark or a light background, e.g. to pick readable colors.
+///
+/// The background color is queried from the terminal, giving up after `timeout`.
+/// If the terminal does not reply, the `COLORFGBG` variable some terminals set is used instead.
+pub fn theme(timeout: Duration) -> Theme {
+    if let Ok(color) = query_bg_color(timeout) {
+        return Theme::from_background(color);
+    }
+    // `COLORFGBG` is `fg;bg` or `fg;default;bg`, with palette indices
+    ::std::env::var("COLORFGBG")
+        .ok()
+        .and_then(|colors| colors.rsplit(';').next()?.parse::<u8>().ok())
+        .map_or(Theme::Unknown, |bg| {
+            Theme::from_background(Color::Indexed(bg))
+        })
+}
+
+/// Set the title of the terminal window. Control characters are left out of the title.
+pub fn set_title(title: &str) -> Result<(), Error> {
+    perform(SetTitle(title))
+}
+
+/// Set the icon name of the terminal window. Control characters are left out of the name.
+pub fn set_icon_name(name: &str) -> Result<(), Error> {
+    perform(SetIconName(name))
+}
+
+/// Save the title and the icon name of the terminal window, so they can be restored with `pop_title`.
+pub fn push_title() -> Result<(), Error> {
+    perform(PushTitle)
+}
+
+/// Restore the title and the icon name of the terminal window saved with `push_title`.
+pub fn pop_title() -> Result<(), Error> {
+    perform(PopTitle)
+}
+
+/// Print `text` as a link to `url`, or only `text` if the terminal is not known to support hyperlinks.
+pub fn print_hyperlink(url: &str, text: &str) -> Result<(), Error> {
+    perform(Hyperlink::new(url, text))
+}
+
+/// Copy `text` to the specified clipboard, through the terminal.
+///
+/// See `CopyToClipboard`.
+pub fn copy_to_clipboard(text: &str, selection: Selection) -> Result<(), Error> {
+    perform(CopyToClipboard(text, selection))
+}
+
+/// Get the text in the specified clipboard, giving up if the terminal does not reply within `timeout`.
+///
+/// Most terminals do not allow reading the clipboard (or only after asking the user), so an `Error::Timeout` is returned.
+/// Not supported on Windows.
+pub fn read_clipboard(selection: Selection, timeout: Duration) -> Result<String, Error> {
+    platform::read_clipboard(selection, timeout)
+}
+
+/// Switch to the alternate screen buffer.
+pub fn enter_alternate_screen() -> Result<(), Error> {
+    perform(EnterAlternateScreen)
+}
+
+/// Switch back from the alternate screen buffer to the normal one.
+pub fn leave_alternate_screen() -> Result<(), Error> {
+    perform(LeaveAlternateScreen)
+}
+
+/// Confine scrolling to the rows from `top` to `bottom` (inclusive).
+///
+/// This also moves the cursor to the top left corner of the screen.
+/// Negative rows, or a `top` that is not above `bottom`, are rejected with an `Error::InvalidArgument`.
+pub fn set_scroll_region(top: i32, bottom: i32) -> Result<(), Error> {
+    execute(ScrollRegion(top, bottom))
+}
+
+/// Make the whole screen scroll again, after `set_scroll_region`.
+///
+/// This also moves the cursor to the top left corner of the screen.
+pub fn reset_scroll_region() -> Result<(), Error> {
+    perform(ResetScrollRegion)
+}
+
+/// Scroll the contents of the scroll region up by `n` lines.
+pub fn scroll_up(n: i32) -> Result<(), Error> {
+    perform(ScrollUp(n))
+}
+
+/// Scroll the contents of the scroll region down by `n` lines.
+pub fn scroll_down(n: i32) -> Result<(), Error> {
+    perform(ScrollDown(n))
+}
+
+/// Insert `n` blank lines at the cursor, shifting the following lines down.
+///
+/// A negative `n` is rejected with an `Error::InvalidArgument`.
+pub fn insert_lines(n: i32) -> Result<(), Error> {
+    execute(InsertLines(n))
+}
+
+/// Delete `n` lines, starting with the line the cursor is on, shifting the following lines up.
+///
+/// A negative `n` is rejected with an `Error::InvalidArgument`.
+pub fn delete_lines(n: i32) -> Result<(), Error> {
+    execute(DeleteLines(n))
+}
+
+/// Insert `n` blank characters at the cursor, shifting the rest of the line right.
+///
+/// A negative `n` is rejected with an `Error::InvalidArgument`.
+pub fn insert_chars(n: i32) -> Result<(), Error> {
+    execute(InsertChars(n))
+}
+
+/// Delete `n` characters, starting with the one the cursor is on, shifting the rest of the line left.
+///
+/// A negative `n` is rejected with an `Error::InvalidArgument`.
+pub fn delete_chars(n: i32) -> Result<(), Error> {
+    execute(DeleteChars(n))
+}
+
+/// Set `n` characters to a space `' '`, starting with the one the cursor is on.
+///
+/// A negative `n` is rejected with an `Error::InvalidArgument`.
+pub fn erase_chars(n: i32) -> Result<(), Error> {
+    execute(EraseChars(n))
+}
+
+/// Get the size of the terminal.
+///
+/// The tuple returned contains the (width, height) of the terminal in characters,
+/// so valid coordinates are in the range `x in 0..width` and `y in 0..height`.
+/// On Windows, this is the size of the console screen buffer.
+pub fn size() -> Result<(i32, i32), Error> {
+    platform::size()
+}
+
 /// Clear the screen, i.e. setting every character in the terminal to a space `' '`.
 pub fn clear() -> Result<(), Error> {
     platform::clear()
 }
+
+/// Clear the part of the terminal selected by `mode`, i.e. setting its characters to a space `' '`.
+pub fn clear_with(mode: ClearMode) -> Result<(), Error> {
+    platform::clear_with(mode)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_movement() {
+        assert_eq!(format!("{}", Goto(3, 4)), "\x1B[5;4H");
+        assert_eq!(format!("{}", Goto(0, 0)), "\x1B[1;1H");
+        assert_eq!(format!("{}", Goto(-1, 2)), "\x1B[3;1H");
+        assert_eq!(format!("{}", Relative(2, -3)), "\x1B[2C\x1B[3A");
+        assert_eq!(format!("{}", Relative(-2, 3)), "\x1B[2D\x1B[3B");
+        assert_eq!(format!("{}", Relative(0, 0)), "");
+        assert_eq!(format!("{}", Left(2)), "\x1B[2D");
+        assert_eq!(format!("{}", Right(2)), "\x1B[2C");
+        assert_eq!(format!("{}", Up(2)), "\x1B[2A");
+        assert_eq!(format!("{}", Down(2)), "\x1B[2B");
+        assert_eq!(format!("{}", Left(i32::MIN)), "\x1B[2147483647C");
+    }
+
+    #[test]
+    fn columns_rows_and_lines() {
+        assert_eq!(format!("{}", Column(4)), "\x1B[5G");
+        assert_eq!(format!("{}", Column(-4)), "\x1B[1G");
+        assert_eq!(format!("{}", Row(4)), "\x1B[5d");
+        assert_eq!(format!("{}", Row(-4)), "\x1B[1d");
+        assert_eq!(format!("{}", NextLine(2)), "\x1B[2E");
+        assert_eq!(format!("{}", NextLine(0)), "\x1B[1G");
+        assert_eq!(format!("{}", PrevLine(2)), "\x1B[2F");
+    }
+
+    #[test]
+    fn clearing() {
+        assert_eq!(format!("{}", Clear), "\x1B[2J");
+        let modes = [
+            (ClearMode::All, "\x1B[2J"),
+            (ClearMode::Below, "\x1B[0J"),
+            (ClearMode::Above, "\x1B[1J"),
+            (ClearMode::Scrollback, "\x1B[3J"),
+            (ClearMode::Line, "\x1B[2K"),
+            (ClearMode::LineLeft, "\x1B[1K"),
+            (ClearMode::LineRight, "\x1B[0K"),
+        ];
+        for &(mode, seq) in &modes {
+            assert_eq!(format!("{}", mode), seq);
+        }
+    }
+
+    #[test]
+    fn cursor_appearance() {
+        assert_eq!(format!("{}", Hide), "\x1B[?25l");
+        assert_eq!(format!("{}", Show), "\x1B[?25h");
+        assert_eq!(format!("{}", CursorStyle::Default), "\x1B[0 q");
+        assert_eq!(format!("{}", CursorStyle::SteadyBar), "\x1B[6 q");
+        assert_eq!(format!("{}", SavePos), "\x1B7");
+        assert_eq!(format!("{}", RestorePos), "\x1B8");
+    }
+
+    #[test]
+    fn counts() {
+        assert_eq!(format!("{}", InsertLines(2)), "\x1B[2L");
+        assert_eq!(format!("{}", DeleteLines(2)), "\x1B[2M");
+        assert_eq!(format!("{}", InsertChars(2)), "\x1B[2@");
+        assert_eq!(format!("{}", DeleteChars(2)), "\x1B[2P");
+        assert_eq!(format!("{}", EraseChars(2)), "\x1B[2X");
+        // A count of 0 would mean 1 to the terminal
+        assert_eq!(format!("{}", InsertLines(0)), "");
+        assert_eq!(format!("{}", DeleteChars(-2)), "");
+    }
+
+    #[test]
+    fn scrolling() {
+        assert_eq!(format!("{}", ScrollRegion(1, 9)), "\x1B[2;10r");
+        assert_eq!(format!("{}", ScrollRegion(5, 3)), "");
+        assert_eq!(format!("{}", ScrollRegion(-1, 3)), "");
+        assert_eq!(format!("{}", ResetScrollRegion), "\x1B[r");
+        assert_eq!(format!("{}", ScrollUp(3)), "\x1B[3S");
+        assert_eq!(format!("{}", ScrollUp(-3)), "\x1B[3T");
+        assert_eq!(format!("{}", ScrollDown(3)), "\x1B[3T");
+        assert_eq!(format!("{}", ScrollDown(0)), "");
+        assert_eq!(format!("{}", Index), "\x1BD");
+        assert_eq!(format!("{}", ReverseIndex), "\x1BM");
+    }
+
+    #[test]
+    fn composes_with_text() {
+        let s = format!("{}a{}b", Goto(1, 1), Right(3));
+        assert_eq!(s, "\x1B[2;2Ha\x1B[3Cb");
+    }
+
+    #[test]
+    fn titles_leave_out_control_characters() {
+        // ESC, BEL and the C1 string terminator could end the sequence early
+        let title = "a\x1B]0;b\x07c\u{9C}d\x1B\\e\ne";
+        assert_eq!(format!("{}", SetTitle(title)), "\x1B]2;a]0;bcd\\ee\x1B\\");
+        assert_eq!(
+            format!("{}", SetIconName(title)),
+            "\x1B]1;a]0;bcd\\ee\x1B\\"
+        );
+        assert_eq!(
+            format!("{}", SetTitleAndIconName(title)),
+            "\x1B]0;a]0;bcd\\ee\x1B\\"
+        );
+    }
+
+    #[test]
+    fn titles_are_pushed_and_popped() {
+        assert_eq!(format!("{}", PushTitle), "\x1B[22;0t");
+        assert_eq!(format!("{}", PopTitle), "\x1B[23;0t");
+    }
+}
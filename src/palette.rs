@@ -0,0 +1,95 @@
+//! Changing the colors of the terminal's palette (OSC 4 / OSC 104).
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{self, Write};
+use std::time::Duration;
+
+use style;
+
+use super::{query_palette_color, reset_palette_color, set_palette_color, Color, Error};
+
+/// A type that when `Display`ed, changes the entry of the 256 color palette at the specified index to the specified color.
+///
+/// Text already printed with the entry changes its color as well.
+/// Named and indexed colors are set to their RGB values in xterm's default palette, not to the colors the user configured.
+/// `Color::Default` resets the entry to the color configured by the user, like `ResetPaletteColor`.
+#[derive(Clone, Copy)]
+pub struct SetPaletteColor(pub u8, pub Color);
+
+impl Display for SetPaletteColor {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        let (r, g, b) = match style::rgb(self.1) {
+            Some(rgb) => rgb,
+            None => return ResetPaletteColor(self.0).fmt(fmt),
+        };
+        write!(
+            fmt,
+            "\x1B]4;{};rgb:{:02x}/{:02x}/{:02x}\x1B\\",
+            self.0, r, g, b
+        )
+    }
+}
+
+/// A type that when `Display`ed, resets the entry of the palette at the specified index to the color configured by the user.
+#[derive(Clone, Copy)]
+pub struct ResetPaletteColor(pub u8);
+
+impl Display for ResetPaletteColor {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "\x1B]104;{}\x1B\\", self.0)
+    }
+}
+
+/// A type that when `Display`ed, resets all entries of the palette to the colors configured by the user.
+#[derive(Clone, Copy)]
+pub struct ResetPalette;
+
+impl Display for ResetPalette {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt.write_str("\x1B]104\x1B\\")
+    }
+}
+
+/// A guard that changes entries of the palette and restores them when dropped.
+///
+/// Before an entry is changed for the first time, its color is queried from the terminal, so it can be restored exactly,
+/// even if it was changed by someone else before. If the terminal does not reply,
+/// the entry is reset to the color configured by the user instead.
+pub struct PaletteGuard {
+    timeout: Duration,
+    // The changed entries and their original colors, if they are known
+    saved: Vec<(u8, Option<Color>)>,
+}
+
+impl PaletteGuard {
+    /// Create a guard that has not changed anything yet.
+    ///
+    /// The terminal gets `timeout` to reply, whenever the original color of an entry is queried.
+    pub fn new(timeout: Duration) -> Self {
+        PaletteGuard {
+            timeout,
+            saved: Vec::new(),
+        }
+    }
+
+    /// Change the entry of the palette at `index` to `color`, until the guard is dropped.
+    pub fn set(&mut self, index: u8, color: Color) -> Result<(), Error> {
+        if !self.saved.iter().any(|&(i, _)| i == index) {
+            let orig = query_palette_color(index, self.timeout).ok();
+            self.saved.push((index, orig));
+        }
+        set_palette_color(index, color)
+    }
+}
+
+impl Drop for PaletteGuard {
+    fn drop(&mut self) {
+        for &(index, orig) in &self.saved {
+            let _ = match orig {
+                Some(color) => set_palette_color(index, color),
+                None => reset_palette_color(index),
+            };
+        }
+        let _ = io::stdout().flush();
+    }
+}
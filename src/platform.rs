@@ -1,6 +1,7 @@
-use std;
+use std::fmt::{Formatter, Result as FmtResult};
+use std::time::Duration;
 
-use super::Error;
+use super::{ClearMode, Color, Error, Pos, Selection};
 
 pub use self::platform_impl::*;
 
@@ -10,10 +11,12 @@ mod platform_impl {
     use super::*;
 
     use std::io::Write;
+    use std::sync::Mutex;
 
-    use self::winapi::{shared::minwindef::{DWORD, FALSE},
-                       um::{processenv, winbase, wincon,
-                            wincon::{CONSOLE_SCREEN_BUFFER_INFOEX as ScreenBufferInfo, COORD},
+    use self::winapi::{shared::minwindef::{DWORD, FALSE, TRUE},
+                       um::{consoleapi, processenv, winbase, wincon,
+                            wincon::{CONSOLE_CURSOR_INFO as CursorInfo,
+                                     CONSOLE_SCREEN_BUFFER_INFOEX as ScreenBufferInfo, COORD},
                             winnt::{HANDLE, WCHAR}}};
 
     fn get_handle() -> Result<HANDLE, Error> {
@@ -46,19 +49,224 @@ mod platform_impl {
         }
     }
 
-    pub fn get_cursor_pos() -> Result<(i32, i32), Error> {
+    pub fn get_cursor_pos() -> Result<Pos, Error> {
         std::io::stdout().flush()?;
         let info = get_screen_buffer_info()?;
-        Ok((
+        Ok(Pos::new(
             info.dwCursorPosition.X as i32,
             info.dwCursorPosition.Y as i32,
         ))
     }
 
+    pub fn get_cursor_pos_timeout(_timeout: Duration) -> Result<Pos, Error> {
+        get_cursor_pos()
+    }
+
+    pub fn probe_truecolor(_timeout: Duration) -> Result<bool, Error> {
+        // Replies are not delivered through the console API
+        Err(Error::PlatformSpecific)
+    }
+
+    pub fn query_fg_color(_timeout: Duration) -> Result<Color, Error> {
+        Err(Error::PlatformSpecific)
+    }
+
+    pub fn query_bg_color(_timeout: Duration) -> Result<Color, Error> {
+        Err(Error::PlatformSpecific)
+    }
+
+    pub fn query_cursor_color(_timeout: Duration) -> Result<Color, Error> {
+        Err(Error::PlatformSpecific)
+    }
+
+    pub fn query_palette_color(_index: u8, _timeout: Duration) -> Result<Color, Error> {
+        Err(Error::PlatformSpecific)
+    }
+
+    pub fn read_clipboard(_selection: Selection, _timeout: Duration) -> Result<String, Error> {
+        Err(Error::PlatformSpecific)
+    }
+
+    pub fn take_pending_input() -> Vec<u8> {
+        // Nothing is read while querying the console
+        Vec::new()
+    }
+
+    pub fn stdout_is_tty() -> bool {
+        let mut mode: DWORD = 0;
+        match get_handle() {
+            Ok(hdl) => unsafe { consoleapi::GetConsoleMode(hdl, &mut mode as *mut _) != FALSE },
+            Err(_) => false,
+        }
+    }
+
+    pub fn size() -> Result<(i32, i32), Error> {
+        let info = get_screen_buffer_info()?;
+        Ok((info.dwSize.X as i32, info.dwSize.Y as i32))
+    }
+
+    pub fn fmt_cursor_pos(_fmt: &mut Formatter, x: i32, y: i32) -> FmtResult {
+        set_cursor_pos(x, y)?;
+        Ok(())
+    }
+
+    // Move the cursor to the position returned by `f`, saturated to the borders of the screen buffer.
+    fn move_cursor<F: FnOnce(Pos) -> Pos>(f: F) -> Result<(), Error> {
+        let pos = f(get_cursor_pos()?).clamp_to(size()?);
+        set_cursor_pos(pos.x, pos.y)
+    }
+
+    pub fn fmt_relative(_fmt: &mut Formatter, x: i32, y: i32) -> FmtResult {
+        move_cursor(|cur| Pos::new(cur.x.saturating_add(x), cur.y.saturating_add(y)))?;
+        Ok(())
+    }
+
+    pub fn fmt_column(_fmt: &mut Formatter, x: i32) -> FmtResult {
+        move_cursor(|cur| Pos::new(x, cur.y))?;
+        Ok(())
+    }
+
+    pub fn fmt_row(_fmt: &mut Formatter, y: i32) -> FmtResult {
+        move_cursor(|cur| Pos::new(cur.x, y))?;
+        Ok(())
+    }
+
+    pub fn fmt_next_line(_fmt: &mut Formatter, n: i32) -> FmtResult {
+        move_cursor(|cur| Pos::new(0, cur.y.saturating_add(n)))?;
+        Ok(())
+    }
+
+    pub fn fmt_clear_with(_fmt: &mut Formatter, mode: ClearMode) -> FmtResult {
+        clear_with(mode)?;
+        Ok(())
+    }
+
+    // The console has no notion of a saved cursor position, so it is emulated.
+    static SAVED_POS: Mutex<Option<Pos>> = Mutex::new(None);
+
+    pub fn fmt_save_pos(_fmt: &mut Formatter) -> FmtResult {
+        let pos = get_cursor_pos()?;
+        *SAVED_POS.lock().map_err(|_| Error::PlatformSpecific)? = Some(pos);
+        Ok(())
+    }
+
+    pub fn fmt_restore_pos(_fmt: &mut Formatter) -> FmtResult {
+        let saved = *SAVED_POS.lock().map_err(|_| Error::PlatformSpecific)?;
+        if let Some(pos) = saved {
+            set_cursor_pos(pos.x, pos.y)?;
+        }
+        Ok(())
+    }
+
+    fn set_cursor_visible(visible: bool) -> Result<(), Error> {
+        std::io::stdout().flush()?;
+        unsafe {
+            let mut info: CursorInfo = std::mem::zeroed();
+            if wincon::GetConsoleCursorInfo(get_handle()?, &mut info as *mut _) == FALSE {
+                return Err(Error::PlatformSpecific);
+            }
+            info.bVisible = if visible { TRUE } else { FALSE };
+            match wincon::SetConsoleCursorInfo(get_handle()?, &info as *const _) {
+                FALSE => Err(Error::PlatformSpecific),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    pub fn fmt_hide(_fmt: &mut Formatter) -> FmtResult {
+        set_cursor_visible(false)?;
+        Ok(())
+    }
+
+    pub fn fmt_show(_fmt: &mut Formatter) -> FmtResult {
+        set_cursor_visible(true)?;
+        Ok(())
+    }
+
+    // Set the title of the console to the null terminated `title`.
+    fn set_console_title(title: &[WCHAR]) -> Result<(), Error> {
+        std::io::stdout().flush()?;
+        match unsafe { wincon::SetConsoleTitleW(title.as_ptr()) } {
+            FALSE => Err(Error::PlatformSpecific),
+            _ => Ok(()),
+        }
+    }
+
+    fn set_title(title: &str) -> Result<(), Error> {
+        let title: Vec<WCHAR> = title
+            .chars()
+            .filter(|c| !c.is_control())
+            .collect::<String>()
+            .encode_utf16()
+            .chain(Some(0))
+            .collect();
+        set_console_title(&title)
+    }
+
+    pub fn fmt_title(_fmt: &mut Formatter, title: &str) -> FmtResult {
+        set_title(title)?;
+        Ok(())
+    }
+
+    pub fn fmt_title_and_icon_name(_fmt: &mut Formatter, title: &str) -> FmtResult {
+        set_title(title)?;
+        Ok(())
+    }
+
+    pub fn fmt_icon_name(_fmt: &mut Formatter, _name: &str) -> FmtResult {
+        // The console has no icon name
+        Ok(())
+    }
+
+    // The console has no stack of titles, so it is emulated.
+    static TITLES: Mutex<Vec<Vec<WCHAR>>> = Mutex::new(Vec::new());
+
+    pub fn fmt_push_title(_fmt: &mut Formatter) -> FmtResult {
+        // Titles are limited to 64K bytes
+        let mut title: Vec<WCHAR> = vec![0; 32 * 1024];
+        let len = unsafe { wincon::GetConsoleTitleW(title.as_mut_ptr(), title.len() as DWORD) };
+        title.truncate(len as usize);
+        title.push(0);
+        let mut titles = TITLES.lock().map_err(|_| Error::PlatformSpecific)?;
+        titles.push(title);
+        Ok(())
+    }
+
+    pub fn fmt_pop_title(_fmt: &mut Formatter) -> FmtResult {
+        let title = TITLES.lock().map_err(|_| Error::PlatformSpecific)?.pop();
+        if let Some(title) = title {
+            set_console_title(&title)?;
+        }
+        Ok(())
+    }
+
     pub fn clear() -> Result<(), Error> {
+        clear_with(ClearMode::All)
+    }
+
+    pub fn clear_with(mode: ClearMode) -> Result<(), Error> {
         let info = get_screen_buffer_info()?;
-        let size = info.dwSize.X as u32 * info.dwSize.Y as u32;
-        let coord = COORD { X: 0, Y: 0 };
+        let (width, height) = (info.dwSize.X as u32, info.dwSize.Y as u32);
+        let (x, y) = (
+            info.dwCursorPosition.X as u32,
+            info.dwCursorPosition.Y as u32,
+        );
+
+        // The first cell to clear and the number of cells to clear from there on
+        let ((start_x, start_y), size) = match mode {
+            ClearMode::All => ((0, 0), width * height),
+            ClearMode::Below => ((x, y), (height - y) * width - x),
+            ClearMode::Above => ((0, 0), y * width + x + 1),
+            // Everything above the visible window
+            ClearMode::Scrollback => ((0, 0), info.srWindow.Top as u32 * width),
+            ClearMode::Line => ((0, y), width),
+            ClearMode::LineLeft => ((0, y), x + 1),
+            ClearMode::LineRight => ((x, y), width - x),
+        };
+        let coord = COORD {
+            X: start_x as i16,
+            Y: start_y as i16,
+        };
         let mut _written: DWORD = 0;
 
         let res = unsafe {
@@ -97,84 +305,253 @@ mod platform_impl {
 mod platform_impl {
     use super::*;
 
+    extern crate libc;
     extern crate termios;
 
-    use self::termios::{tcsetattr, Termios, CREAD, ECHO, ICANON, TCSAFLUSH};
-    use std::io::{Read, Write};
-
-    const FD_STDIN: ::std::os::unix::io::RawFd = 1;
-
-    pub fn set_cursor_pos(x: i32, y: i32) -> Result<(), Error> {
-        std::io::stdout().write_fmt(format_args!("\x1B[{};{}H", y, x))?;
-        Ok(())
+    use self::termios::{cfmakeraw, tcsetattr, Termios, ECHO, ICANON, TCSADRAIN};
+
+    use std::fmt::Display;
+    use std::fs::File;
+    use std::io::{self, Write};
+    use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+    use std::sync::Mutex;
+
+    use reply::Replies;
+    use terminal;
+    use {ansi, Terminal};
+
+    /// A guard that changes the mode of a terminal and restores the original mode when dropped.
+    ///
+    /// The original mode is restored on every path out of the scope of the guard, including early returns and panics
+    /// (unless the program is built with `panic = "abort"`).
+    ///
+    /// Only available on *NIX.
+    pub struct RawModeGuard {
+        fd: RawFd,
+        orig: Termios,
     }
 
-    pub fn get_cursor_pos() -> Result<(i32, i32), Error> {
-        let mut stdout = std::io::stdout();
+    impl RawModeGuard {
+        /// Put the terminal behind `fd` into cbreak mode.
+        ///
+        /// In cbreak mode, input is available byte by byte instead of line by line and is not echoed,
+        /// but everything else, like signal generating keys such as `Ctrl+C`, keeps working.
+        pub fn cbreak(fd: RawFd) -> Result<Self, Error> {
+            RawModeGuard::enter(fd, |mode| mode.c_lflag &= !(ICANON | ECHO))
+        }
 
-        // Set noncanonical mode
-        let orig = Termios::from_fd(FD_STDIN)?;
-        let mut noncan = orig.clone();
-        noncan.c_lflag &= !ICANON;
-        noncan.c_lflag &= !ECHO;
-        noncan.c_lflag &= !CREAD;
-        tcsetattr(FD_STDIN, TCSAFLUSH, &noncan)?;
+        /// Put the terminal behind `fd` into raw mode.
+        ///
+        /// In raw mode, input is available byte by byte, is not echoed, and no input or output processing is done at all.
+        pub fn raw(fd: RawFd) -> Result<Self, Error> {
+            RawModeGuard::enter(fd, cfmakeraw)
+        }
 
-        // Write command
-        stdout.write(b"\x1B[6n")?;
-        stdout.flush()?;
+        fn enter<F: FnOnce(&mut Termios)>(fd: RawFd, change: F) -> Result<Self, Error> {
+            let orig = Termios::from_fd(fd)?;
+            let mut mode = orig;
+            change(&mut mode);
+            tcsetattr(fd, TCSADRAIN, &mode)?;
+            Ok(RawModeGuard { fd, orig })
+        }
 
-        let mut buf = [0u8; 2];
+        /// Restore the original mode of the terminal now.
+        ///
+        /// Unlike dropping the guard, this reports whether restoring succeeded.
+        pub fn restore(self) -> Result<(), Error> {
+            let res = tcsetattr(self.fd, TCSADRAIN, &self.orig);
+            ::std::mem::forget(self);
+            Ok(res?)
+        }
+    }
 
-        // Expect `ESC[`
-        std::io::stdin().read_exact(&mut buf)?;
-        if buf[0] != 0x1B || buf[1] as char != '[' {
-            return Err(Error::PlatformSpecific);
+    impl Drop for RawModeGuard {
+        fn drop(&mut self) {
+            let _ = tcsetattr(self.fd, TCSADRAIN, &self.orig);
         }
+    }
 
-        // Read rows and cols through a ad-hoc integer parsing function
-        let read_num = || -> Result<(i32, char), Error> {
-            let mut num = 0;
-            let mut c;
-
-            loop {
-                let mut buf = [0u8; 1];
-                std::io::stdin().read_exact(&mut buf)?;
-                c = buf[0] as char;
-                if let Some(d) = c.to_digit(10) {
-                    num = if num == 0 { 0 } else { num * 10 };
-                    num += d as i32;
-                } else {
-                    break;
-                }
-            }
+    // Get the terminal that queries are sent to.
+    // This is the controlling terminal if there is one, so queries work even if `stdin` / `stdout` are redirected.
+    fn query_terminal() -> Result<Terminal<File, File>, Error> {
+        if let Ok(term) = Terminal::tty() {
+            return Ok(term);
+        }
 
-            Ok((num, c))
+        let dup = |fd| match unsafe { libc::dup(fd) } {
+            -1 => Err(io::Error::last_os_error()),
+            fd => Ok(unsafe { File::from_raw_fd(fd) }),
         };
+        Ok(Terminal::new(
+            dup(libc::STDIN_FILENO)?,
+            dup(libc::STDOUT_FILENO)?,
+        ))
+    }
 
-        // Read rows and expect `;`
-        let (rows, c) = read_num()?;
-        if c != ';' {
-            return Err(Error::PlatformSpecific);
+    // The state of reading replies, shared by all queries, so input that did not belong to a reply
+    // and late replies are not lost with the terminal of a single query.
+    static REPLIES: Mutex<Option<Replies>> = Mutex::new(None);
+
+    // Run `f` on the query terminal, which is set to cbreak mode in the meantime, so replies can be read.
+    fn query<T, F>(f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Terminal<File, File>) -> Result<T, Error>,
+    {
+        // Everything written so far has to reach the terminal before it is queried
+        io::stdout().flush()?;
+
+        let mut replies = REPLIES.lock().unwrap_or_else(|err| err.into_inner());
+        let replies = replies.get_or_insert_with(Replies::new);
+        let mut term = query_terminal()?;
+        let guard = RawModeGuard::cbreak(term.reader().as_raw_fd())?;
+        terminal::swap_replies(&mut term, replies);
+        let res = f(&mut term);
+        terminal::swap_replies(&mut term, replies);
+        guard.restore()?;
+        res
+    }
+
+    pub fn take_pending_input() -> Vec<u8> {
+        let mut replies = REPLIES.lock().unwrap_or_else(|err| err.into_inner());
+        replies.get_or_insert_with(Replies::new).take_pending()
+    }
+
+    // How long `size` waits for each reply, if it has to ask the terminal.
+    const SIZE_TIMEOUT: Duration = Duration::from_secs(1);
+
+    // Ask the kernel for the window size of the terminal behind `fd`.
+    pub fn window_size(fd: RawFd) -> Option<(i32, i32)> {
+        let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+        match unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut ws as *mut _) } {
+            0 if ws.ws_col > 0 && ws.ws_row > 0 => Some((ws.ws_col as i32, ws.ws_row as i32)),
+            _ => None,
         }
+    }
 
-        // Read cols
-        let (cols, c) = read_num()?;
+    pub fn set_cursor_pos(x: i32, y: i32) -> Result<(), Error> {
+        Terminal::stdio().set_pos(x, y)
+    }
 
-        // Expect `R`
-        let res = if c == 'R' {
-            Ok((cols, rows))
-        } else {
-            Err(Error::PlatformSpecific)
-        };
+    pub fn get_cursor_pos() -> Result<Pos, Error> {
+        query(|term| term.get_pos())
+    }
 
-        // Reset terminal
-        tcsetattr(FD_STDIN, TCSAFLUSH, &orig)?;
-        res
+    pub fn get_cursor_pos_timeout(timeout: Duration) -> Result<Pos, Error> {
+        query(|term| term.get_pos_timeout(timeout))
+    }
+
+    pub fn probe_truecolor(timeout: Duration) -> Result<bool, Error> {
+        query(|term| term.probe_truecolor(timeout))
+    }
+
+    pub fn query_fg_color(timeout: Duration) -> Result<Color, Error> {
+        query(|term| term.query_fg_color(timeout))
+    }
+
+    pub fn query_bg_color(timeout: Duration) -> Result<Color, Error> {
+        query(|term| term.query_bg_color(timeout))
+    }
+
+    pub fn query_cursor_color(timeout: Duration) -> Result<Color, Error> {
+        query(|term| term.query_cursor_color(timeout))
+    }
+
+    pub fn query_palette_color(index: u8, timeout: Duration) -> Result<Color, Error> {
+        query(|term| term.query_palette_color(index, timeout))
+    }
+
+    pub fn read_clipboard(selection: Selection, timeout: Duration) -> Result<String, Error> {
+        query(|term| term.read_clipboard(selection, timeout))
+    }
+
+    pub fn stdout_is_tty() -> bool {
+        unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 }
+    }
+
+    pub fn size() -> Result<(i32, i32), Error> {
+        let std_fds = [libc::STDOUT_FILENO, libc::STDIN_FILENO, libc::STDERR_FILENO];
+        if let Some(size) = std_fds.iter().filter_map(|&fd| window_size(fd)).next() {
+            return Ok(size);
+        }
+
+        // All standard streams are redirected, try the controlling terminal
+        if let Some(size) = File::open("/dev/tty")
+            .ok()
+            .and_then(|tty| window_size(tty.as_raw_fd()))
+        {
+            return Ok(size);
+        }
+
+        // Not a terminal the kernel knows the size of, ask the terminal itself,
+        // without hanging if it is not a terminal at all
+        query(|term| term.size_timeout(SIZE_TIMEOUT))
     }
 
     pub fn clear() -> Result<(), Error> {
-        std::io::stdout().write(b"\x1Bc")?;
-        Ok(())
+        Terminal::stdio().clear()
+    }
+
+    pub fn clear_with(mode: ClearMode) -> Result<(), Error> {
+        Terminal::stdio().execute(&mode)
+    }
+
+    pub fn fmt_cursor_pos(fmt: &mut Formatter, x: i32, y: i32) -> FmtResult {
+        ansi::CursorPos(x, y).fmt(fmt)
+    }
+
+    pub fn fmt_relative(fmt: &mut Formatter, x: i32, y: i32) -> FmtResult {
+        ansi::CursorMove(x, y).fmt(fmt)
+    }
+
+    pub fn fmt_column(fmt: &mut Formatter, x: i32) -> FmtResult {
+        ansi::CursorColumn(x).fmt(fmt)
+    }
+
+    pub fn fmt_row(fmt: &mut Formatter, y: i32) -> FmtResult {
+        ansi::CursorRow(y).fmt(fmt)
+    }
+
+    pub fn fmt_next_line(fmt: &mut Formatter, n: i32) -> FmtResult {
+        ansi::CursorNextLine(n).fmt(fmt)
+    }
+
+    pub fn fmt_clear_with(fmt: &mut Formatter, mode: ClearMode) -> FmtResult {
+        fmt.write_str(ansi::clear(mode))
+    }
+
+    pub fn fmt_save_pos(fmt: &mut Formatter) -> FmtResult {
+        fmt.write_str(ansi::SAVE_POS)
+    }
+
+    pub fn fmt_restore_pos(fmt: &mut Formatter) -> FmtResult {
+        fmt.write_str(ansi::RESTORE_POS)
+    }
+
+    pub fn fmt_hide(fmt: &mut Formatter) -> FmtResult {
+        fmt.write_str(ansi::HIDE)
+    }
+
+    pub fn fmt_show(fmt: &mut Formatter) -> FmtResult {
+        fmt.write_str(ansi::SHOW)
+    }
+
+    pub fn fmt_title(fmt: &mut Formatter, title: &str) -> FmtResult {
+        ansi::Title(2, title).fmt(fmt)
+    }
+
+    pub fn fmt_title_and_icon_name(fmt: &mut Formatter, title: &str) -> FmtResult {
+        ansi::Title(0, title).fmt(fmt)
+    }
+
+    pub fn fmt_icon_name(fmt: &mut Formatter, name: &str) -> FmtResult {
+        ansi::Title(1, name).fmt(fmt)
+    }
+
+    pub fn fmt_push_title(fmt: &mut Formatter) -> FmtResult {
+        fmt.write_str(ansi::PUSH_TITLE)
+    }
+
+    pub fn fmt_pop_title(fmt: &mut Formatter) -> FmtResult {
+        fmt.write_str(ansi::POP_TITLE)
     }
 }
@@ -0,0 +1,326 @@
+//! Parsing of the replies terminals send in response to queries.
+
+#[cfg(unix)]
+use std::str;
+
+use super::Pos;
+
+const ESC: u8 = 0x1B;
+const BEL: u8 = 0x07;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+    // An OSC or DCS string, terminated by `ST` (or `BEL` for OSC).
+    Str,
+    // An `ESC` inside of a string, which is the start of `ST` if followed by `\`.
+    StrEscape,
+}
+
+/// A byte-wise parser for escape sequences in the input stream of a terminal.
+///
+/// Bytes that are not part of an escape sequence, as well as malformed sequences, are unrelated to any reply
+/// (e.g. keys pressed by the user while waiting for the terminal) and get handed back to the caller.
+pub struct Parser {
+    state: State,
+    seq: Vec<u8>,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Parser {
+            state: State::Ground,
+            seq: Vec::new(),
+        }
+    }
+
+    /// Feed a single byte to the parser.
+    ///
+    /// Returns the raw bytes of an escape sequence, once it is complete.
+    /// Unrelated bytes are appended to `unrelated`.
+    pub fn push(&mut self, byte: u8, unrelated: &mut Vec<u8>) -> Option<Vec<u8>> {
+        match self.state {
+            State::Ground if byte == ESC => {
+                self.seq.push(byte);
+                self.state = State::Escape;
+            }
+            State::Ground => unrelated.push(byte),
+            State::Escape => match byte {
+                b'[' => {
+                    self.seq.push(byte);
+                    self.state = State::Csi;
+                }
+                b']' | b'P' => {
+                    self.seq.push(byte);
+                    self.state = State::Str;
+                }
+                _ => {
+                    // Not a reply, e.g. a key pressed together with alt
+                    self.abort(unrelated);
+                    return self.push(byte, unrelated);
+                }
+            },
+            State::Csi => match byte {
+                // Parameter and intermediate bytes
+                0x20..=0x3F => self.seq.push(byte),
+                // Final byte
+                0x40..=0x7E => {
+                    self.seq.push(byte);
+                    return Some(self.finish());
+                }
+                _ => {
+                    self.abort(unrelated);
+                    return self.push(byte, unrelated);
+                }
+            },
+            State::Str if byte == BEL && self.seq[1] == b']' => {
+                self.seq.push(byte);
+                return Some(self.finish());
+            }
+            State::Str if byte == ESC => self.state = State::StrEscape,
+            State::Str => self.seq.push(byte),
+            State::StrEscape if byte == b'\\' => {
+                self.seq.push(ESC);
+                self.seq.push(byte);
+                return Some(self.finish());
+            }
+            State::StrEscape => {
+                // The string was cancelled by the start of another escape sequence
+                self.abort(unrelated);
+                self.state = State::Escape;
+                self.seq.push(ESC);
+                return self.push(byte, unrelated);
+            }
+        }
+        None
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        self.state = State::Ground;
+        ::std::mem::take(&mut self.seq)
+    }
+
+    fn abort(&mut self, unrelated: &mut Vec<u8>) {
+        self.state = State::Ground;
+        unrelated.append(&mut self.seq);
+    }
+}
+
+/// The state of reading replies from a terminal, that carries over from one query to the next.
+pub struct Replies {
+    parser: Parser,
+    pending: Vec<u8>,
+    // Cursor position requests that timed out, whose replies may still arrive
+    late_cursor_pos: usize,
+}
+
+impl Replies {
+    pub fn new() -> Self {
+        Replies {
+            parser: Parser::new(),
+            pending: Vec::new(),
+            late_cursor_pos: 0,
+        }
+    }
+
+    /// Feed a single byte of input, returning the reply once a sequence is complete that `accept` takes.
+    ///
+    /// Everything else is buffered as pending input.
+    pub fn feed<T, F: Fn(&[u8]) -> Option<T>>(&mut self, byte: u8, accept: &F) -> Option<T> {
+        let seq = self.parser.push(byte, &mut self.pending)?;
+        if self.late_cursor_pos > 0 && cursor_pos(&seq).is_some() {
+            // The reply to a request that timed out must not be taken for the reply to a later one
+            self.late_cursor_pos -= 1;
+            return None;
+        }
+        let reply = accept(&seq);
+        if reply.is_none() {
+            self.pending.extend(seq);
+        }
+        reply
+    }
+
+    /// Take the input that was buffered, because it did not belong to a reply.
+    pub fn take_pending(&mut self) -> Vec<u8> {
+        ::std::mem::take(&mut self.pending)
+    }
+
+    /// Note that a cursor position request timed out, so its reply is dropped if it arrives later.
+    #[cfg(unix)]
+    pub fn cursor_pos_timed_out(&mut self) {
+        self.late_cursor_pos += 1;
+    }
+}
+
+/// Split a complete control sequence `ESC [ params final` into its parameter bytes and final byte.
+pub fn csi(seq: &[u8]) -> Option<(&[u8], u8)> {
+    if seq.len() >= 3 && seq[0] == ESC && seq[1] == b'[' {
+        Some((&seq[2..seq.len() - 1], seq[seq.len() - 1]))
+    } else {
+        None
+    }
+}
+
+/// Parse a list of numeric parameters separated by `;`.
+pub fn numbers(params: &[u8]) -> Option<Vec<i32>> {
+    params
+        .split(|&b| b == b';')
+        .map(|p| {
+            if p.is_empty() || !p.iter().all(u8::is_ascii_digit) {
+                return None;
+            }
+            p.iter().try_fold(0i32, |n, &d| {
+                n.checked_mul(10)?.checked_add((d - b'0') as i32)
+            })
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+/// Get the data of a complete device control string `ESC P data ESC \\`.
+pub fn dcs(seq: &[u8]) -> Option<&[u8]> {
+    if seq.len() >= 4 && seq.starts_with(b"\x1BP") && seq.ends_with(b"\x1B\\") {
+        Some(&seq[2..seq.len() - 2])
+    } else {
+        None
+    }
+}
+
+#[cfg(unix)]
+/// Get the data of a complete operating system command `ESC ] data BEL` or `ESC ] data ESC \\`.
+pub fn osc(seq: &[u8]) -> Option<&[u8]> {
+    if !seq.starts_with(b"\x1B]") {
+        return None;
+    }
+    let terminator = if seq.ends_with(&[BEL]) {
+        1
+    } else if seq.ends_with(b"\x1B\\") {
+        2
+    } else {
+        return None;
+    };
+    seq.get(2..seq.len() - terminator)
+}
+
+#[cfg(unix)]
+/// Parse a color report `OSC param ; rgb:r/g/b ST` for the given `param` (e.g. `11` for the background color).
+pub fn color(seq: &[u8], param: &str) -> Option<(u8, u8, u8)> {
+    let data = osc(seq)?;
+    if !data.starts_with(param.as_bytes()) || data.get(param.len()) != Some(&b';') {
+        return None;
+    }
+    let spec = &data[param.len() + 1..];
+    let spec = spec
+        .strip_prefix(b"rgb:")
+        .or_else(|| spec.strip_prefix(b"rgba:"))?;
+    let mut channels = spec.split(|&b| b == b'/').map(channel);
+    Some((channels.next()??, channels.next()??, channels.next()??))
+}
+
+#[cfg(unix)]
+// Scale a color channel given with 1 to 4 hex digits to 8 bits.
+fn channel(hex: &[u8]) -> Option<u8> {
+    if hex.is_empty() || hex.len() > 4 || !hex.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    let value = u32::from_str_radix(str::from_utf8(hex).ok()?, 16).ok()?;
+    let max = (1 << (4 * hex.len())) - 1;
+    Some(((value * 255 + max / 2) / max) as u8)
+}
+
+#[cfg(unix)]
+/// Get the base64 encoded contents of a clipboard report `OSC 52 ; selection ; data ST`.
+pub fn clipboard(seq: &[u8]) -> Option<&[u8]> {
+    let data = osc(seq)?.strip_prefix(b"52;")?;
+    let start = data.iter().position(|&b| b == b';')? + 1;
+    Some(&data[start..])
+}
+
+#[cfg(unix)]
+/// Parse a `DECRQSS` reply `DCS valid $ r setting ST` into the reported setting, or `Some(None)` if the request was invalid.
+pub fn setting(seq: &[u8]) -> Option<Option<&[u8]>> {
+    let data = dcs(seq)?;
+    match data.get(..3)? {
+        b"1$r" => Some(Some(&data[3..])),
+        b"0$r" => Some(None),
+        _ => None,
+    }
+}
+
+#[cfg(unix)]
+/// Get the parameters out of a setting reported for `SGR`, like `0;1;38;2;10;20;30m`.
+pub fn sgr(setting: &[u8]) -> Option<&[u8]> {
+    let params = setting.strip_suffix(b"m")?;
+    if params
+        .iter()
+        .all(|&b| b.is_ascii_digit() || b == b';' || b == b':')
+    {
+        Some(params)
+    } else {
+        None
+    }
+}
+
+/// Parse a cursor position report `ESC [ row ; col R` into a zero-based position.
+pub fn cursor_pos(seq: &[u8]) -> Option<Pos> {
+    match csi(seq) {
+        Some((params, b'R')) => match numbers(params)?[..] {
+            // The reported coordinates are one-based
+            [row, col] => Some(Pos::new(col - 1, row - 1)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_with_either_terminator() {
+        let rgb = Some((0x12, 0x34, 0x56));
+        assert_eq!(color(b"\x1B]11;rgb:1212/3434/5656\x1B\\", "11"), rgb);
+        assert_eq!(color(b"\x1B]11;rgb:1212/3434/5656\x07", "11"), rgb);
+    }
+
+    #[test]
+    fn color_channels_are_scaled_to_8_bits() {
+        assert_eq!(color(b"\x1B]10;rgb:f/8/0\x07", "10"), Some((255, 136, 0)));
+        assert_eq!(
+            color(b"\x1B]10;rgb:ff/80/00\x07", "10"),
+            Some((255, 128, 0))
+        );
+        assert_eq!(
+            color(b"\x1B]10;rgb:fff/800/000\x07", "10"),
+            Some((255, 128, 0))
+        );
+        assert_eq!(
+            color(b"\x1B]10;rgb:ffff/8080/0000\x07", "10"),
+            Some((255, 128, 0))
+        );
+        assert_eq!(
+            color(b"\x1B]10;rgba:ffff/0/0/ffff\x07", "10"),
+            Some((255, 0, 0))
+        );
+    }
+
+    #[test]
+    fn palette_color() {
+        let seq = b"\x1B]4;42;rgb:0000/8787/d7d7\x1B\\";
+        assert_eq!(color(seq, "4;42"), Some((0, 135, 215)));
+        assert_eq!(color(seq, "4;4"), None);
+    }
+
+    #[test]
+    fn invalid_colors() {
+        assert_eq!(color(b"\x1B]11;rgb:1212/3434/5656\x07", "10"), None);
+        assert_eq!(color(b"\x1B]11;rgb:1212/3434\x07", "11"), None);
+        assert_eq!(color(b"\x1B]11;rgb:12345/0/0\x07", "11"), None);
+        assert_eq!(color(b"\x1B]11;rgb:xx/0/0\x07", "11"), None);
+        assert_eq!(color(b"\x1B]11;#123456\x07", "11"), None);
+        assert_eq!(color(b"\x1B[11;rgb:1/2/3\x07", "11"), None);
+    }
+}
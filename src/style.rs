@@ -0,0 +1,574 @@
+//! Colors and attributes of the text printed to the terminal (SGR).
+
+use std::fmt::{self, Display, Formatter, Result as FmtResult};
+
+use capabilities::{capabilities, Capabilities, ColorSupport};
+
+/// A color of the text or its background.
+///
+/// The first 16 colors are the ones of the terminal's palette, so their exact appearance depends on the user's theme.
+/// Colors the terminal can not display are replaced by the nearest one it can (see `Capabilities`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Color {
+    /// The default color of the terminal.
+    Default,
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+    /// An entry of the 256 color palette.
+    ///
+    /// `0..=15` are the colors above, `16..=231` a 6x6x6 color cube and `232..=255` a grayscale ramp.
+    Indexed(u8),
+    /// A 24-bit color, given as (red, green, blue).
+    Rgb(u8, u8, u8),
+}
+
+// The named colors, in the order of their palette index.
+const NAMED: [Color; 16] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::White,
+    Color::BrightBlack,
+    Color::BrightRed,
+    Color::BrightGreen,
+    Color::BrightYellow,
+    Color::BrightBlue,
+    Color::BrightMagenta,
+    Color::BrightCyan,
+    Color::BrightWhite,
+];
+
+// The named colors as configured by default in xterm, to approximate other colors with.
+const NAMED_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+// The levels of each channel in the 6x6x6 color cube of the 256 color palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl Color {
+    // The palette index of one of the 16 named colors.
+    fn named_index(self) -> Option<u8> {
+        NAMED.iter().position(|&c| c == self).map(|i| i as u8)
+    }
+
+    // The color to write to a terminal with the given color support, approximating colors it can not display.
+    fn downgrade(self, colors: ColorSupport) -> Option<Color> {
+        match (self, colors) {
+            // Not a color, but a reset of it
+            (Color::Default, _) => Some(self),
+            (_, ColorSupport::NoColor) => None,
+            (Color::Rgb(r, g, b), ColorSupport::Ansi256) => {
+                Some(Color::Indexed(nearest_indexed((r, g, b))))
+            }
+            (Color::Rgb(r, g, b), ColorSupport::Ansi16) => Some(nearest_named((r, g, b))),
+            (Color::Indexed(i), ColorSupport::Ansi16) if i < 16 => Some(NAMED[i as usize]),
+            (Color::Indexed(i), ColorSupport::Ansi16) => Some(nearest_named(indexed_rgb(i))),
+            _ => Some(self),
+        }
+    }
+}
+
+/// The RGB value of `color`, approximating the named colors by the defaults of xterm.
+pub fn rgb(color: Color) -> Option<(u8, u8, u8)> {
+    match (color, color.named_index()) {
+        (Color::Rgb(r, g, b), _) => Some((r, g, b)),
+        (Color::Indexed(i), _) => Some(indexed_rgb(i)),
+        (_, Some(i)) => Some(NAMED_RGB[i as usize]),
+        _ => None,
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> i32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2);
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+// The RGB value of an entry of the 256 color palette above the named colors.
+fn indexed_rgb(i: u8) -> (u8, u8, u8) {
+    match i {
+        0..=15 => NAMED_RGB[i as usize],
+        16..=231 => {
+            let i = (i - 16) as usize;
+            (
+                CUBE_LEVELS[i / 36],
+                CUBE_LEVELS[i / 6 % 6],
+                CUBE_LEVELS[i % 6],
+            )
+        }
+        _ => {
+            let level = 8 + (i - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+fn nearest_named(rgb: (u8, u8, u8)) -> Color {
+    let nearest = (0..16)
+        .min_by_key(|&i| distance(rgb, NAMED_RGB[i]))
+        .unwrap_or(0);
+    NAMED[nearest]
+}
+
+// The nearest entry in the color cube or grayscale ramp. The named colors are left out, since they depend on the theme.
+fn nearest_indexed(rgb: (u8, u8, u8)) -> u8 {
+    let level = |v: u8| {
+        (0..6)
+            .min_by_key(|&i| (CUBE_LEVELS[i] as i32 - v as i32).abs())
+            .unwrap_or(0) as u8
+    };
+    let cube = 16 + 36 * level(rgb.0) + 6 * level(rgb.1) + level(rgb.2);
+    let gray = (232..=255)
+        .min_by_key(|&i| distance(rgb, indexed_rgb(i)))
+        .unwrap_or(232);
+    if distance(rgb, indexed_rgb(gray)) < distance(rgb, indexed_rgb(cube)) {
+        gray
+    } else {
+        cube
+    }
+}
+
+/// Whether the terminal has a dark or a light background.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Theme {
+    /// Light text on a dark background.
+    Dark,
+    /// Dark text on a light background.
+    Light,
+    /// The background color could not be determined.
+    Unknown,
+}
+
+impl Theme {
+    /// Classify a background color by its brightness.
+    pub fn from_background(color: Color) -> Self {
+        let (r, g, b) = match rgb(color) {
+            Some(rgb) => rgb,
+            None => return Theme::Unknown,
+        };
+        // The relative luminance, weighted by how bright each channel appears
+        let luma = 0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32;
+        if luma < 128.0 {
+            Theme::Dark
+        } else {
+            Theme::Light
+        }
+    }
+}
+
+/// The way text is underlined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Underline {
+    /// A single straight line.
+    Single,
+    /// Two straight lines.
+    Double,
+    /// A wavy line, as used for spelling mistakes and compiler diagnostics.
+    Curly,
+    /// A dotted line.
+    Dotted,
+    /// A dashed line.
+    Dashed,
+}
+
+const BOLD: u16 = 1;
+const DIM: u16 = 1 << 1;
+const ITALIC: u16 = 1 << 2;
+const BLINK: u16 = 1 << 3;
+const REVERSE: u16 = 1 << 4;
+const HIDDEN: u16 = 1 << 5;
+const STRIKETHROUGH: u16 = 1 << 6;
+
+/// The colors and attributes of text.
+///
+/// When `Display`ed, the style is applied to all text printed afterwards, on top of the current one.
+/// Attributes and colors that are not set are left as they are, use `Reset` to go back to the default style.
+/// Like the other types of this crate, it composes with them in a single `print!`,
+/// e.g. `print!("{}{}error{}", Goto(0, 10), Style::new().fg(Color::Red).bold(), Reset)`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Style {
+    fg: Option<Color>,
+    bg: Option<Color>,
+    underline: Option<Underline>,
+    underline_color: Option<Color>,
+    attrs: u16,
+}
+
+impl Style {
+    /// Create a style that changes nothing.
+    pub fn new() -> Self {
+        Style::default()
+    }
+
+    /// Set the color of the text.
+    pub fn fg(mut self, color: Color) -> Self {
+        self.fg = Some(color);
+        self
+    }
+
+    /// Set the color of the background.
+    pub fn bg(mut self, color: Color) -> Self {
+        self.bg = Some(color);
+        self
+    }
+
+    /// Make the text bold, or brighter on some terminals.
+    pub fn bold(self) -> Self {
+        self.attr(BOLD)
+    }
+
+    /// Make the text dim / faint.
+    pub fn dim(self) -> Self {
+        self.attr(DIM)
+    }
+
+    /// Make the text italic.
+    pub fn italic(self) -> Self {
+        self.attr(ITALIC)
+    }
+
+    /// Underline the text with a single line.
+    pub fn underline(self) -> Self {
+        self.underline_style(Underline::Single)
+    }
+
+    /// Underline the text in the given way.
+    ///
+    /// Everything but `Single` is degraded to a single line,
+    /// if the terminal does not support extended underlines (see `Capabilities`).
+    pub fn underline_style(mut self, underline: Underline) -> Self {
+        self.underline = Some(underline);
+        self
+    }
+
+    /// Set the color of the underline, separately from the color of the text.
+    ///
+    /// This is left out if the terminal does not support extended underlines (see `Capabilities`),
+    /// so the underline has the color of the text.
+    pub fn underline_color(mut self, color: Color) -> Self {
+        self.underline_color = Some(color);
+        self
+    }
+
+    /// Make the text blink.
+    pub fn blink(self) -> Self {
+        self.attr(BLINK)
+    }
+
+    /// Swap the colors of the text and the background.
+    pub fn reverse(self) -> Self {
+        self.attr(REVERSE)
+    }
+
+    /// Make the text invisible, while still taking up space.
+    pub fn hidden(self) -> Self {
+        self.attr(HIDDEN)
+    }
+
+    /// Cross out the text.
+    pub fn strikethrough(self) -> Self {
+        self.attr(STRIKETHROUGH)
+    }
+
+    fn attr(mut self, attr: u16) -> Self {
+        self.attrs |= attr;
+        self
+    }
+}
+
+impl Style {
+    // Write the sequence for a terminal with the capabilities `caps`.
+    fn fmt_with(&self, caps: Capabilities, fmt: &mut Formatter) -> FmtResult {
+        let mut params = Params::new(fmt);
+        let attrs = [
+            (BOLD, 1),
+            (DIM, 2),
+            (ITALIC, 3),
+            (BLINK, 5),
+            (REVERSE, 7),
+            (HIDDEN, 8),
+            (STRIKETHROUGH, 9),
+        ];
+        for &(attr, code) in attrs.iter() {
+            if self.attrs & attr != 0 {
+                params.push(format_args!("{}", code))?;
+            }
+        }
+        match (self.underline, caps.extended_underline) {
+            (Some(Underline::Single), _) => params.push(format_args!("4"))?,
+            (Some(Underline::Double), true) => params.push(format_args!("4:2"))?,
+            (Some(Underline::Curly), true) => params.push(format_args!("4:3"))?,
+            (Some(Underline::Dotted), true) => params.push(format_args!("4:4"))?,
+            (Some(Underline::Dashed), true) => params.push(format_args!("4:5"))?,
+            (Some(_), false) => params.push(format_args!("4"))?,
+            (None, _) => {}
+        }
+        if let Some(color) = self.fg.and_then(|c| c.downgrade(caps.colors)) {
+            fmt_color(&mut params, color, 38)?;
+        }
+        if let Some(color) = self.bg.and_then(|c| c.downgrade(caps.colors)) {
+            fmt_color(&mut params, color, 48)?;
+        }
+        match self.underline_color.and_then(|c| c.downgrade(caps.colors)) {
+            Some(color) if caps.extended_underline => fmt_color(&mut params, color, 58)?,
+            _ => {}
+        }
+        params.finish()
+    }
+}
+
+impl Display for Style {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        self.fmt_with(capabilities(), fmt)
+    }
+}
+
+/// A type that when `Display`ed, resets all colors and attributes of the text to the default.
+#[derive(Clone, Copy)]
+pub struct Reset;
+
+impl Display for Reset {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt.write_str("\x1B[0m")
+    }
+}
+
+// Write `color` for the layer selected by `extended`, which is the parameter introducing
+// an indexed or RGB color for the layer (`38` for the text, `48` for the background, `58` for the underline).
+fn fmt_color(params: &mut Params, color: Color, extended: u8) -> FmtResult {
+    match (color, color.named_index()) {
+        (Color::Default, _) => params.push(format_args!("{}", extended + 1)),
+        // There are no short forms for the underline color
+        (_, Some(i)) if extended == 58 => params.push(format_args!("58;5;{}", i)),
+        // `30..=37` / `40..=47` for the normal colors, `90..=97` / `100..=107` for the bright ones
+        (_, Some(i)) if i < 8 => params.push(format_args!("{}", extended - 8 + i)),
+        (_, Some(i)) => params.push(format_args!("{}", extended + 52 + i - 8)),
+        (Color::Indexed(i), _) => params.push(format_args!("{};5;{}", extended, i)),
+        (Color::Rgb(r, g, b), _) => params.push(format_args!("{};2;{};{};{}", extended, r, g, b)),
+        _ => Ok(()),
+    }
+}
+
+// Joins the parameters of a single SGR sequence, writing nothing if there are none.
+struct Params<'a, 'b: 'a> {
+    fmt: &'a mut Formatter<'b>,
+    empty: bool,
+}
+
+impl<'a, 'b> Params<'a, 'b> {
+    fn new(fmt: &'a mut Formatter<'b>) -> Self {
+        Params { fmt, empty: true }
+    }
+
+    fn push(&mut self, param: fmt::Arguments) -> FmtResult {
+        self.fmt.write_str(if self.empty { "\x1B[" } else { ";" })?;
+        self.empty = false;
+        self.fmt.write_fmt(param)
+    }
+
+    fn finish(self) -> FmtResult {
+        if self.empty {
+            Ok(())
+        } else {
+            self.fmt.write_str("m")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Format `style` for a terminal with the given capabilities.
+    fn sgr(style: Style, colors: ColorSupport, extended_underline: bool) -> String {
+        struct With(Style, Capabilities);
+
+        impl Display for With {
+            fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+                self.0.fmt_with(self.1, fmt)
+            }
+        }
+
+        let caps = Capabilities {
+            colors,
+            extended_underline,
+            hyperlinks: false,
+        };
+        With(style, caps).to_string()
+    }
+
+    fn full(style: Style) -> String {
+        sgr(style, ColorSupport::TrueColor, true)
+    }
+
+    #[test]
+    fn named_colors() {
+        assert_eq!(full(Style::new().fg(Color::Black)), "\x1B[30m");
+        assert_eq!(full(Style::new().fg(Color::White)), "\x1B[37m");
+        assert_eq!(full(Style::new().bg(Color::Red)), "\x1B[41m");
+        assert_eq!(full(Style::new().fg(Color::BrightBlack)), "\x1B[90m");
+        assert_eq!(full(Style::new().fg(Color::BrightWhite)), "\x1B[97m");
+        assert_eq!(full(Style::new().bg(Color::BrightRed)), "\x1B[101m");
+        assert_eq!(full(Style::new().bg(Color::BrightWhite)), "\x1B[107m");
+        assert_eq!(
+            full(Style::new().underline_color(Color::BrightRed)),
+            "\x1B[58;5;9m"
+        );
+    }
+
+    #[test]
+    fn indexed_rgb_and_default_colors() {
+        assert_eq!(full(Style::new().fg(Color::Indexed(100))), "\x1B[38;5;100m");
+        assert_eq!(full(Style::new().bg(Color::Indexed(100))), "\x1B[48;5;100m");
+        assert_eq!(
+            full(Style::new().fg(Color::Rgb(1, 2, 3))),
+            "\x1B[38;2;1;2;3m"
+        );
+        assert_eq!(
+            full(Style::new().underline_color(Color::Rgb(1, 2, 3))),
+            "\x1B[58;2;1;2;3m"
+        );
+        assert_eq!(full(Style::new().fg(Color::Default)), "\x1B[39m");
+        assert_eq!(full(Style::new().bg(Color::Default)), "\x1B[49m");
+        assert_eq!(
+            full(Style::new().underline_color(Color::Default)),
+            "\x1B[59m"
+        );
+    }
+
+    #[test]
+    fn colors_are_degraded() {
+        let style = Style::new().fg(Color::Rgb(255, 0, 0));
+        assert_eq!(sgr(style, ColorSupport::Ansi256, true), "\x1B[38;5;196m");
+        assert_eq!(sgr(style, ColorSupport::Ansi16, true), "\x1B[91m");
+        assert_eq!(sgr(style, ColorSupport::NoColor, true), "");
+    }
+
+    #[test]
+    fn parameters_are_joined() {
+        assert_eq!(full(Style::new()), "");
+        let style = Style::new()
+            .bold()
+            .strikethrough()
+            .underline()
+            .fg(Color::Red)
+            .bg(Color::Indexed(20))
+            .underline_color(Color::Blue);
+        assert_eq!(full(style), "\x1B[1;9;4;31;48;5;20;58;5;4m");
+    }
+
+    #[test]
+    fn extended_underlines() {
+        let curly = Style::new().underline_style(Underline::Curly);
+        assert_eq!(full(curly), "\x1B[4:3m");
+        assert_eq!(
+            full(Style::new().underline_style(Underline::Double)),
+            "\x1B[4:2m"
+        );
+        assert_eq!(full(Style::new().underline()), "\x1B[4m");
+        // Without support, the style is degraded to a single line and the color is left out
+        let colored = curly.underline_color(Color::Red);
+        assert_eq!(sgr(colored, ColorSupport::TrueColor, false), "\x1B[4m");
+        assert_eq!(
+            sgr(
+                Style::new().underline_color(Color::Red),
+                ColorSupport::TrueColor,
+                false
+            ),
+            ""
+        );
+    }
+
+    #[test]
+    fn nearest_indexed_finds_exact_entries() {
+        for i in 16..=255 {
+            assert_eq!(nearest_indexed(indexed_rgb(i)), i);
+        }
+    }
+
+    #[test]
+    fn nearest_indexed_approximates() {
+        assert_eq!(nearest_indexed((1, 2, 3)), 16);
+        assert_eq!(nearest_indexed((250, 0, 10)), 196);
+        assert_eq!(nearest_indexed((100, 140, 170)), 67);
+        // Grays are closer to the grayscale ramp than to the cube
+        assert_eq!(nearest_indexed((125, 126, 130)), 244);
+    }
+
+    #[test]
+    fn downgrade_to_256_colors() {
+        let colors = ColorSupport::Ansi256;
+        assert_eq!(
+            Color::Rgb(255, 0, 0).downgrade(colors),
+            Some(Color::Indexed(196))
+        );
+        assert_eq!(
+            Color::Indexed(100).downgrade(colors),
+            Some(Color::Indexed(100))
+        );
+        assert_eq!(Color::Red.downgrade(colors), Some(Color::Red));
+    }
+
+    #[test]
+    fn downgrade_to_16_colors() {
+        let colors = ColorSupport::Ansi16;
+        assert_eq!(
+            Color::Rgb(250, 5, 5).downgrade(colors),
+            Some(Color::BrightRed)
+        );
+        assert_eq!(Color::Rgb(200, 0, 0).downgrade(colors), Some(Color::Red));
+        assert_eq!(Color::Indexed(9).downgrade(colors), Some(Color::BrightRed));
+        assert_eq!(Color::Indexed(21).downgrade(colors), Some(Color::Blue));
+        assert_eq!(Color::Indexed(255).downgrade(colors), Some(Color::White));
+    }
+
+    #[test]
+    fn downgrade_to_no_colors() {
+        assert_eq!(Color::Red.downgrade(ColorSupport::NoColor), None);
+        assert_eq!(Color::Rgb(1, 2, 3).downgrade(ColorSupport::NoColor), None);
+        // Resetting a color is always fine
+        assert_eq!(
+            Color::Default.downgrade(ColorSupport::NoColor),
+            Some(Color::Default)
+        );
+    }
+
+    #[test]
+    fn true_color_is_unchanged() {
+        let color = Color::Rgb(10, 20, 30);
+        assert_eq!(color.downgrade(ColorSupport::TrueColor), Some(color));
+    }
+}
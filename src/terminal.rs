@@ -0,0 +1,783 @@
+#[cfg(unix)]
+extern crate libc;
+
+use std::fmt::Display;
+#[cfg(unix)]
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Stdin, Stdout, Write};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+#[cfg(unix)]
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+use clipboard::{self, RequestClipboard};
+#[cfg(unix)]
+use platform;
+use reply::{self, Replies};
+use tracked::{self, CursorState};
+
+use super::{
+    ansi, Clear, ClearMode, Column, CopyToClipboard, CursorStyle, DeleteChars, DeleteLines, Direct,
+    Down, EnterAlternateScreen, EraseChars, Error, Goto, Hide, Hyperlink, Index, InsertChars,
+    InsertLines, LeaveAlternateScreen, Left, NextLine, PopTitle, Pos, PrevLine, PushTitle,
+    Relative, Reset, ResetPalette, ResetPaletteColor, ResetScrollRegion, RestorePos, ReverseIndex,
+    Right, Row, SavePos, ScrollDown, ScrollRegion, ScrollUp, SetIconName, SetPaletteColor,
+    SetTitle, SetTitleAndIconName, Show, Style, Up,
+};
+#[cfg(unix)]
+use super::{Color, Selection};
+
+/// A handle to an ANSI terminal that is driven through an arbitrary reader and writer.
+///
+/// Escape sequences are written to `W` and replies to queries (like the cursor position report) are read from `R`.
+/// This makes it possible to target something other than the process' standard streams,
+/// e.g. a `/dev/tty` handle, the master side of a pty, a socket or a `Vec<u8>` in tests.
+///
+/// The terminal mode of the device behind `R` is left untouched.
+/// For queries to work, it has to be in noncanonical mode, otherwise the reply is only delivered after a newline.
+///
+/// Input that arrives before the reply to a query (e.g. keys pressed by the user) is not discarded,
+/// but buffered and can be retrieved with `take_pending_input`.
+/// Replies to cursor position requests that timed out are dropped when they arrive later,
+/// so they are not taken for the reply to the next request.
+pub struct Terminal<R, W> {
+    reader: R,
+    writer: W,
+    replies: Replies,
+}
+
+impl Terminal<Stdin, Stdout> {
+    /// Create a terminal that operates on the process' `stdin` and `stdout`.
+    pub fn stdio() -> Self {
+        Terminal::new(io::stdin(), io::stdout())
+    }
+}
+
+#[cfg(unix)]
+impl Terminal<File, File> {
+    /// Create a terminal that operates on the controlling terminal of the process, `/dev/tty`.
+    ///
+    /// Unlike `stdio`, this keeps working if `stdin` or `stdout` are redirected,
+    /// e.g. when the program is used in a pipeline like `cat x | program | less`.
+    /// The `Capabilities` are still detected from `stdout` though, use `set_capabilities` for colors and hyperlinks.
+    pub fn tty() -> Result<Self, Error> {
+        let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        Ok(Terminal::new(tty.try_clone()?, tty))
+    }
+}
+
+impl<R: Read, W: Write> Terminal<R, W> {
+    /// Create a terminal that reads replies from `reader` and writes escape sequences to `writer`.
+    pub fn new(reader: R, writer: W) -> Self {
+        Terminal {
+            reader,
+            writer,
+            replies: Replies::new(),
+        }
+    }
+
+    /// Get a reference to the underlying reader.
+    pub fn reader(&self) -> &R {
+        &self.reader
+    }
+
+    /// Get a mutable reference to the underlying reader.
+    pub fn reader_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Get a reference to the underlying writer.
+    pub fn writer(&self) -> &W {
+        &self.writer
+    }
+
+    /// Get a mutable reference to the underlying writer.
+    pub fn writer_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Consume the terminal, returning the underlying reader and writer.
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+
+    /// Take the input that was read while waiting for the reply to a query, but did not belong to it.
+    pub fn take_pending_input(&mut self) -> Vec<u8> {
+        self.replies.take_pending()
+    }
+
+    /// Perform `cmd` on this terminal.
+    pub fn execute<C: Command>(&mut self, cmd: &C) -> Result<(), Error> {
+        cmd.apply(self)
+    }
+
+    /// Set the cursor position to the specified coordinates.
+    ///
+    /// Negative coordinates are rejected with an `Error::InvalidArgument`.
+    pub fn set_pos(&mut self, x: i32, y: i32) -> Result<(), Error> {
+        if x < 0 || y < 0 {
+            return Err(Error::InvalidArgument);
+        }
+        write!(self.writer, "{}", ansi::CursorPos(x, y))?;
+        Ok(())
+    }
+
+    /// Get the current cursor position.
+    pub fn get_pos(&mut self) -> Result<Pos, Error> {
+        // Write command
+        self.writer.write_all(ansi::REQUEST_CURSOR_POS.as_bytes())?;
+        self.writer.flush()?;
+
+        self.read_reply(reply::cursor_pos)
+    }
+
+    // Determine the size of the terminal, with `get_pos` to query the cursor position.
+    fn size_with<F>(&mut self, mut get_pos: F) -> Result<(i32, i32), Error>
+    where
+        F: FnMut(&mut Self) -> Result<Pos, Error>,
+    {
+        let orig = get_pos(self)?;
+        self.set_pos(9998, 9998)?;
+        let corner = get_pos(self);
+        self.set_pos(orig.x, orig.y)?;
+        let corner = corner?;
+        Ok((corner.x + 1, corner.y + 1))
+    }
+
+    /// Clear the screen, i.e. setting every character in the terminal to a space `' '`.
+    pub fn clear(&mut self) -> Result<(), Error> {
+        self.writer
+            .write_all(ansi::clear(ClearMode::All).as_bytes())?;
+        Ok(())
+    }
+
+    // Read input until a reply is found that is accepted by `accept`, buffering everything else.
+    fn read_reply<T, F: Fn(&[u8]) -> Option<T>>(&mut self, accept: F) -> Result<T, Error> {
+        loop {
+            let mut buf = [0u8; 1];
+            self.reader.read_exact(&mut buf)?;
+            if let Some(reply) = self.replies.feed(buf[0], &accept) {
+                return Ok(reply);
+            }
+        }
+    }
+}
+
+impl<R: WindowSize, W: Write> Terminal<R, W> {
+    /// Get the size of the terminal.
+    ///
+    /// The tuple returned contains the (width, height) of the terminal in characters.
+    /// The size is taken from `R::window_size` if possible. Otherwise, it is determined by moving the cursor
+    /// to the bottom right corner and querying its position. The cursor is moved back to where it was afterwards.
+    pub fn size(&mut self) -> Result<(i32, i32), Error> {
+        match self.reader.window_size() {
+            Some(size) => Ok(size),
+            None => self.size_with(Terminal::get_pos),
+        }
+    }
+
+    /// Set the cursor position to the specified coordinates, if they lie within the terminal.
+    ///
+    /// Otherwise, the cursor is not moved and an `Error::OutOfBounds` is returned.
+    pub fn set_pos_checked(&mut self, x: i32, y: i32) -> Result<(), Error> {
+        let pos = Pos::new(x, y).check(self.size()?)?;
+        self.set_pos(pos.x, pos.y)
+    }
+
+    /// Set the cursor position to the specified coordinates, saturated to the last column / row of the terminal.
+    ///
+    /// The position the cursor was moved to is returned.
+    pub fn set_pos_clamped(&mut self, x: i32, y: i32) -> Result<Pos, Error> {
+        let pos = Pos::new(x, y).clamp_to(self.size()?);
+        self.set_pos(pos.x, pos.y)?;
+        Ok(pos)
+    }
+
+    /// Move the cursor by the specified amounts, if it ends up within the terminal.
+    ///
+    /// Otherwise, the cursor is not moved and an `Error::OutOfBounds` is returned.
+    pub fn move_by_checked(&mut self, x: i32, y: i32) -> Result<(), Error> {
+        let cur = self.get_pos()?;
+        self.set_pos_checked(cur.x.saturating_add(x), cur.y.saturating_add(y))
+    }
+
+    /// Move the cursor by the specified amounts, saturated to the borders of the terminal.
+    ///
+    /// The position the cursor was moved to is returned.
+    pub fn move_by_clamped(&mut self, x: i32, y: i32) -> Result<Pos, Error> {
+        let cur = self.get_pos()?;
+        self.set_pos_clamped(cur.x.saturating_add(x), cur.y.saturating_add(y))
+    }
+}
+
+/// A reader of a `Terminal` that may be able to tell the size of the terminal, without asking the terminal itself.
+///
+/// On *NIX, this is implemented for every reader with a file descriptor, which is asked for its window size
+/// (`TIOCGWINSZ`). On other platforms, it is implemented for every reader, but never knows the size.
+pub trait WindowSize: Read {
+    /// Get the (width, height) of the terminal in characters, if it is known.
+    fn window_size(&self) -> Option<(i32, i32)>;
+}
+
+#[cfg(unix)]
+impl<T: Read + AsRawFd> WindowSize for T {
+    fn window_size(&self) -> Option<(i32, i32)> {
+        platform::window_size(self.as_raw_fd())
+    }
+}
+
+#[cfg(not(unix))]
+impl<T: Read> WindowSize for T {
+    fn window_size(&self) -> Option<(i32, i32)> {
+        None
+    }
+}
+
+// Exchange the state of reading replies of `term` with `replies`, so it can be carried over to another terminal.
+#[cfg(unix)]
+pub fn swap_replies<R, W>(term: &mut Terminal<R, W>, replies: &mut Replies) {
+    ::std::mem::swap(&mut term.replies, replies);
+}
+
+#[cfg(unix)]
+impl<R: Read + AsRawFd, W: Write> Terminal<R, W> {
+    /// Get the current cursor position, giving up if the terminal does not reply within `timeout`.
+    ///
+    /// If the reply does not arrive in time, an `Error::Timeout` is returned.
+    pub fn get_pos_timeout(&mut self, timeout: Duration) -> Result<Pos, Error> {
+        self.writer.write_all(ansi::REQUEST_CURSOR_POS.as_bytes())?;
+        self.writer.flush()?;
+        let res = self.read_reply_timeout(timeout, reply::cursor_pos);
+        if let Err(Error::Timeout) = res {
+            self.replies.cursor_pos_timed_out();
+        }
+        res
+    }
+
+    /// Get the color of the text, giving up if the terminal does not reply within `timeout`.
+    ///
+    /// The color is always a `Color::Rgb`. Terminals that do not support the query (`OSC 10`) never reply,
+    /// so an `Error::Timeout` is returned.
+    pub fn query_fg_color(&mut self, timeout: Duration) -> Result<Color, Error> {
+        self.query_color("10", timeout)
+    }
+
+    /// Get the color of the background, giving up if the terminal does not reply within `timeout`.
+    ///
+    /// See `query_fg_color`.
+    pub fn query_bg_color(&mut self, timeout: Duration) -> Result<Color, Error> {
+        self.query_color("11", timeout)
+    }
+
+    /// Get the color of the cursor, giving up if the terminal does not reply within `timeout`.
+    ///
+    /// See `query_fg_color`.
+    pub fn query_cursor_color(&mut self, timeout: Duration) -> Result<Color, Error> {
+        self.query_color("12", timeout)
+    }
+
+    /// Get the color of the entry of the 256 color palette at `index`,
+    /// giving up if the terminal does not reply within `timeout`.
+    ///
+    /// See `query_fg_color`.
+    pub fn query_palette_color(&mut self, index: u8, timeout: Duration) -> Result<Color, Error> {
+        self.query_color(&format!("4;{}", index), timeout)
+    }
+
+    fn query_color(&mut self, param: &str, timeout: Duration) -> Result<Color, Error> {
+        write!(self.writer, "\x1B]{};?\x07", param)?;
+        self.writer.flush()?;
+        self.read_reply_timeout(timeout, |seq| {
+            reply::color(seq, param).map(|(r, g, b)| Color::Rgb(r, g, b))
+        })
+    }
+
+    /// Get the text in the specified clipboard, giving up if the terminal does not reply within `timeout`.
+    ///
+    /// Most terminals do not allow reading the clipboard (or only after asking the user),
+    /// and never reply, so an `Error::Timeout` is returned.
+    pub fn read_clipboard(
+        &mut self,
+        selection: Selection,
+        timeout: Duration,
+    ) -> Result<String, Error> {
+        write!(self.writer, "{}", RequestClipboard(selection))?;
+        self.writer.flush()?;
+        let data =
+            self.read_reply_timeout(timeout, |seq| clipboard::decode(reply::clipboard(seq)?))?;
+        Ok(String::from_utf8_lossy(&data).into_owned())
+    }
+
+    /// Check whether the terminal supports 24-bit RGB colors, by setting one and asking the terminal to report it (`DECRQSS`).
+    ///
+    /// Terminals that do not reply within `timeout`, or approximate the color with their palette, do not support RGB colors.
+    /// The attributes of the text are queried before and restored afterwards, so they are left as they were.
+    pub fn probe_truecolor(&mut self, timeout: Duration) -> Result<bool, Error> {
+        // A terminal that can not report the current attributes can not report the probe either,
+        // so it is not sent at all, which leaves nothing to restore.
+        self.writer.write_all(ansi::REQUEST_SGR.as_bytes())?;
+        self.writer.flush()?;
+        let current = self.read_reply_timeout(timeout, |seq| {
+            Some(
+                reply::setting(seq)?
+                    .and_then(reply::sgr)
+                    .map(<[u8]>::to_vec),
+            )
+        });
+        let current = match current {
+            Ok(Some(sgr)) => sgr,
+            Ok(None) | Err(Error::Timeout) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+
+        self.writer.write_all(ansi::PROBE_TRUECOLOR.as_bytes())?;
+        self.writer.flush()?;
+        let reply = self.read_reply_timeout(timeout, |seq| {
+            let sgr = reply::setting(seq)?
+                .and_then(reply::sgr)
+                .unwrap_or_default();
+            // The parameters may be separated by `;` or `:`
+            Some(sgr.windows(8).any(|w| w == b"10;20;30" || w == b"10:20:30"))
+        });
+        // The reported attributes do not necessarily start with a reset
+        self.writer.write_all(b"\x1B[0;")?;
+        self.writer.write_all(&current)?;
+        self.writer.write_all(b"m")?;
+        self.writer.flush()?;
+        match reply {
+            Err(Error::Timeout) => Ok(false),
+            res => res,
+        }
+    }
+
+    /// Get the size of the terminal, giving up if the terminal does not reply to a query within `timeout`.
+    ///
+    /// See `size`. If a reply does not arrive in time, an `Error::Timeout` is returned.
+    pub fn size_timeout(&mut self, timeout: Duration) -> Result<(i32, i32), Error> {
+        match self.reader.window_size() {
+            Some(size) => Ok(size),
+            None => self.size_with(|term| term.get_pos_timeout(timeout)),
+        }
+    }
+
+    // Like `read_reply`, but waits at most `timeout` for the reply.
+    //
+    // The file descriptor is read directly, because data sitting in the buffer of a buffered reader
+    // (like `Stdin`) would be invisible to `poll`.
+    fn read_reply_timeout<T, F: Fn(&[u8]) -> Option<T>>(
+        &mut self,
+        timeout: Duration,
+        accept: F,
+    ) -> Result<T, Error> {
+        let deadline = Instant::now() + timeout;
+        let fd = self.reader.as_raw_fd();
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let mut pfd = libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let millis = remaining.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+            match unsafe { libc::poll(&mut pfd, 1, millis) } {
+                0 => return Err(Error::Timeout),
+                n if n < 0 => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Err(err.into());
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            let mut byte = 0u8;
+            match unsafe { libc::read(fd, &mut byte as *mut u8 as *mut libc::c_void, 1) } {
+                1 => {}
+                0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                _ => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Err(err.into());
+                    }
+                    continue;
+                }
+            }
+
+            if let Some(reply) = self.replies.feed(byte, &accept) {
+                return Ok(reply);
+            }
+        }
+    }
+}
+
+impl<R, W: Write> Write for Terminal<R, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// An operation that can be performed on a `Terminal`.
+///
+/// All the `Display` types of this crate implement this trait,
+/// so they can target any terminal instead of just the default one.
+pub trait Command: Display {
+    /// Perform the operation on `term`.
+    ///
+    /// By default, this writes the `Display` representation of the command to the terminal.
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        write!(term.writer_mut(), "{}", self)?;
+        Ok(())
+    }
+
+    /// Update `state` with the effect the operation has on the cursor, for a `TrackedTerminal`.
+    ///
+    /// By default, the state is left unchanged.
+    fn track(&self, state: &mut CursorState) {
+        let _ = state;
+    }
+}
+
+impl Command for Goto {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        let Goto(x, y) = *self;
+        term.set_pos(x, y)
+    }
+
+    fn track(&self, state: &mut CursorState) {
+        state.move_to(Pos::new(self.0, self.1));
+    }
+}
+
+impl Command for Relative {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        let Relative(x, y) = *self;
+        write!(term.writer_mut(), "{}", ansi::CursorMove(x, y))?;
+        Ok(())
+    }
+
+    fn track(&self, state: &mut CursorState) {
+        let pos = state.pos;
+        state.move_to(Pos::new(
+            pos.x.saturating_add(self.0),
+            pos.y.saturating_add(self.1),
+        ));
+    }
+}
+
+impl Command for Left {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        Relative(self.0.saturating_neg(), 0).apply(term)
+    }
+
+    fn track(&self, state: &mut CursorState) {
+        Relative(self.0.saturating_neg(), 0).track(state)
+    }
+}
+
+impl Command for Right {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        Relative(self.0, 0).apply(term)
+    }
+
+    fn track(&self, state: &mut CursorState) {
+        Relative(self.0, 0).track(state)
+    }
+}
+
+impl Command for Up {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        Relative(0, self.0.saturating_neg()).apply(term)
+    }
+
+    fn track(&self, state: &mut CursorState) {
+        Relative(0, self.0.saturating_neg()).track(state)
+    }
+}
+
+impl Command for Down {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        Relative(0, self.0).apply(term)
+    }
+
+    fn track(&self, state: &mut CursorState) {
+        Relative(0, self.0).track(state)
+    }
+}
+
+impl Command for Column {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        if self.0 < 0 {
+            return Err(Error::InvalidArgument);
+        }
+        write!(term.writer_mut(), "{}", ansi::CursorColumn(self.0))?;
+        Ok(())
+    }
+
+    fn track(&self, state: &mut CursorState) {
+        let pos = state.pos;
+        state.move_to(Pos::new(self.0, pos.y));
+    }
+}
+
+impl Command for Row {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        if self.0 < 0 {
+            return Err(Error::InvalidArgument);
+        }
+        write!(term.writer_mut(), "{}", ansi::CursorRow(self.0))?;
+        Ok(())
+    }
+
+    fn track(&self, state: &mut CursorState) {
+        let pos = state.pos;
+        state.move_to(Pos::new(pos.x, self.0));
+    }
+}
+
+impl Command for NextLine {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        write!(term.writer_mut(), "{}", ansi::CursorNextLine(self.0))?;
+        Ok(())
+    }
+
+    fn track(&self, state: &mut CursorState) {
+        let pos = state.pos;
+        state.move_to(Pos::new(0, pos.y.saturating_add(self.0)));
+    }
+}
+
+impl Command for PrevLine {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        NextLine(self.0.saturating_neg()).apply(term)
+    }
+
+    fn track(&self, state: &mut CursorState) {
+        NextLine(self.0.saturating_neg()).track(state)
+    }
+}
+
+impl Command for Clear {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        term.clear()
+    }
+}
+
+impl Command for SavePos {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        term.writer_mut().write_all(ansi::SAVE_POS.as_bytes())?;
+        Ok(())
+    }
+
+    fn track(&self, state: &mut CursorState) {
+        state.saved = state.pos;
+    }
+}
+
+impl Command for RestorePos {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        term.writer_mut().write_all(ansi::RESTORE_POS.as_bytes())?;
+        Ok(())
+    }
+
+    fn track(&self, state: &mut CursorState) {
+        let saved = state.saved;
+        state.move_to(saved);
+    }
+}
+
+impl Command for Hide {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        term.writer_mut().write_all(ansi::HIDE.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl Command for Show {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        term.writer_mut().write_all(ansi::SHOW.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl Command for CursorStyle {}
+
+impl Command for ClearMode {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        term.writer_mut().write_all(ansi::clear(*self).as_bytes())?;
+        Ok(())
+    }
+}
+
+impl Command for EnterAlternateScreen {
+    fn track(&self, state: &mut CursorState) {
+        state.saved = state.pos;
+    }
+}
+
+impl Command for LeaveAlternateScreen {
+    fn track(&self, state: &mut CursorState) {
+        let saved = state.saved;
+        state.move_to(saved);
+    }
+}
+
+impl Command for ScrollRegion {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        let ScrollRegion(top, bottom) = *self;
+        if top < 0 || bottom <= top {
+            return Err(Error::InvalidArgument);
+        }
+        write!(term.writer_mut(), "{}", self)?;
+        Ok(())
+    }
+
+    fn track(&self, state: &mut CursorState) {
+        state.move_to(Pos::new(0, 0));
+    }
+}
+
+impl Command for ResetScrollRegion {
+    fn track(&self, state: &mut CursorState) {
+        state.move_to(Pos::new(0, 0));
+    }
+}
+
+impl Command for ScrollUp {}
+
+impl Command for ScrollDown {}
+
+impl Command for Index {
+    fn track(&self, state: &mut CursorState) {
+        let pos = state.pos;
+        state.move_to(Pos::new(pos.x, pos.y + 1));
+    }
+}
+
+impl Command for ReverseIndex {
+    fn track(&self, state: &mut CursorState) {
+        let pos = state.pos;
+        state.move_to(Pos::new(pos.x, pos.y - 1));
+    }
+}
+
+// Write `cmd`, which takes the count `n`, to `term`, rejecting negative counts.
+fn apply_count<C: Display, R: Read, W: Write>(
+    term: &mut Terminal<R, W>,
+    cmd: &C,
+    n: i32,
+) -> Result<(), Error> {
+    if n < 0 {
+        return Err(Error::InvalidArgument);
+    }
+    write!(term.writer_mut(), "{}", cmd)?;
+    Ok(())
+}
+
+impl Command for InsertLines {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        apply_count(term, self, self.0)
+    }
+
+    fn track(&self, state: &mut CursorState) {
+        let pos = state.pos;
+        state.move_to(Pos::new(0, pos.y));
+    }
+}
+
+impl Command for DeleteLines {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        apply_count(term, self, self.0)
+    }
+
+    fn track(&self, state: &mut CursorState) {
+        let pos = state.pos;
+        state.move_to(Pos::new(0, pos.y));
+    }
+}
+
+impl Command for InsertChars {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        apply_count(term, self, self.0)
+    }
+}
+
+impl Command for DeleteChars {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        apply_count(term, self, self.0)
+    }
+}
+
+impl Command for EraseChars {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        apply_count(term, self, self.0)
+    }
+}
+
+impl<'a> Command for SetTitle<'a> {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        write!(term.writer_mut(), "{}", ansi::Title(2, self.0))?;
+        Ok(())
+    }
+}
+
+impl<'a> Command for SetIconName<'a> {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        write!(term.writer_mut(), "{}", ansi::Title(1, self.0))?;
+        Ok(())
+    }
+}
+
+impl<'a> Command for SetTitleAndIconName<'a> {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        write!(term.writer_mut(), "{}", ansi::Title(0, self.0))?;
+        Ok(())
+    }
+}
+
+impl<'a> Command for Hyperlink<'a> {
+    fn track(&self, state: &mut CursorState) {
+        tracked::print(state, self.text);
+    }
+}
+
+impl<'a> Command for CopyToClipboard<'a> {}
+
+impl Command for PushTitle {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        term.writer_mut().write_all(ansi::PUSH_TITLE.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl Command for PopTitle {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        term.writer_mut().write_all(ansi::POP_TITLE.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl Command for SetPaletteColor {}
+
+impl Command for ResetPaletteColor {}
+
+impl Command for ResetPalette {}
+
+impl Command for Style {}
+
+impl Command for Reset {}
+
+impl<C: Command> Command for Direct<C> {
+    fn apply<R: Read, W: Write>(&self, term: &mut Terminal<R, W>) -> Result<(), Error> {
+        self.0.apply(term)
+    }
+
+    fn track(&self, state: &mut CursorState) {
+        self.0.track(state)
+    }
+}
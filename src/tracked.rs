@@ -0,0 +1,352 @@
+extern crate unicode_width;
+
+use std::io::{self, Read, Write};
+use std::str;
+
+use self::unicode_width::UnicodeWidthChar;
+
+use reply::Parser;
+
+use super::{Command, Error, Pos, Terminal, WindowSize};
+
+/// The state of the cursor, as tracked by a `TrackedTerminal`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CursorState {
+    /// The position of the cursor.
+    pub pos: Pos,
+    /// The position saved by `SavePos`.
+    pub saved: Pos,
+    /// The (width, height) of the terminal.
+    pub size: (i32, i32),
+    // Whether the last column was just printed to, so the next character wraps to the next line.
+    wrap_pending: bool,
+}
+
+impl CursorState {
+    /// Create the state of a cursor at `pos` in a terminal of the given (width, height).
+    pub fn new(pos: Pos, size: (i32, i32)) -> Self {
+        CursorState {
+            pos,
+            saved: Pos::new(0, 0),
+            size,
+            wrap_pending: false,
+        }
+    }
+
+    /// Move the cursor to `pos`, saturated to the borders of the terminal.
+    pub fn move_to(&mut self, pos: Pos) {
+        self.pos = pos.clamp_to(self.size);
+        self.wrap_pending = false;
+    }
+
+    // Advance the state by a character printed to the terminal.
+    fn print(&mut self, c: char) {
+        let (width, height) = self.size;
+        match c {
+            // With the usual output processing of a tty, `\n` is turned into `\r\n`
+            '\n' => self.move_to(Pos::new(0, self.pos.y + 1)),
+            '\r' => self.move_to(Pos::new(0, self.pos.y)),
+            '\t' => self.move_to(Pos::new((self.pos.x / 8 + 1) * 8, self.pos.y)),
+            '\x08' => self.move_to(Pos::new(self.pos.x - 1, self.pos.y)),
+            c => {
+                let w = c.width().unwrap_or(0) as i32;
+                if w == 0 {
+                    return;
+                }
+                if self.wrap_pending || self.pos.x + w > width {
+                    self.move_to(Pos::new(0, self.pos.y + 1));
+                }
+                if self.pos.x + w >= width {
+                    self.pos.x = width - 1;
+                    self.wrap_pending = true;
+                } else {
+                    self.pos.x += w;
+                }
+                // Printing in the last row scrolls the screen instead of moving the cursor out
+                self.pos.y = self.pos.y.min(height - 1);
+            }
+        }
+    }
+}
+
+/// Advance `state` by `text` printed to the terminal.
+pub fn print(state: &mut CursorState, text: &str) {
+    for c in text.chars() {
+        state.print(c);
+    }
+}
+
+/// A `Terminal` that keeps track of the cursor position, to avoid round-trips to the terminal.
+///
+/// The position is updated after every command performed with `execute` and after all text written through it,
+/// taking the width of Unicode characters, `\n`, `\r`, tabs and wrapping at the end of a line into account.
+/// Control sequences (`ESC [ ...`) written as text are skipped, but their effects are not tracked,
+/// so all cursor movement has to go through `execute`.
+///
+/// `get_pos` answers from the tracked state. If the tracked state may be off
+/// (e.g. because the terminal was resized or something else wrote to it), use `resync` to query the real position.
+pub struct TrackedTerminal<R, W> {
+    term: Terminal<R, W>,
+    state: CursorState,
+    parser: Parser,
+    // The start of a UTF-8 sequence, that was split over multiple writes
+    partial: Vec<u8>,
+}
+
+impl<R: WindowSize, W: Write> TrackedTerminal<R, W> {
+    /// Start tracking the cursor of `term`, querying its current position and size.
+    ///
+    /// See `Terminal::size` for how the size is determined.
+    pub fn new(mut term: Terminal<R, W>) -> Result<Self, Error> {
+        let size = term.size()?;
+        let pos = term.get_pos()?;
+        Ok(TrackedTerminal::with_state(
+            term,
+            CursorState::new(pos, size),
+        ))
+    }
+}
+
+impl<R: Read, W: Write> TrackedTerminal<R, W> {
+    /// Start tracking the cursor of `term`, assuming that it is in `state`.
+    pub fn with_state(term: Terminal<R, W>, state: CursorState) -> Self {
+        TrackedTerminal {
+            term,
+            state,
+            parser: Parser::new(),
+            partial: Vec::new(),
+        }
+    }
+
+    /// Get the tracked state of the cursor.
+    pub fn state(&self) -> CursorState {
+        self.state
+    }
+
+    /// Get the tracked cursor position.
+    pub fn get_pos(&self) -> Pos {
+        self.state.pos
+    }
+
+    /// Get the size of the terminal, as used for tracking.
+    pub fn size(&self) -> (i32, i32) {
+        self.state.size
+    }
+
+    /// Set the size of the terminal, e.g. after it was resized.
+    pub fn set_size(&mut self, size: (i32, i32)) {
+        self.state.size = size;
+        let pos = self.state.pos;
+        self.state.move_to(pos);
+    }
+
+    /// Query the real cursor position from the terminal and continue tracking from there.
+    pub fn resync(&mut self) -> Result<Pos, Error> {
+        self.term.flush()?;
+        let pos = self.term.get_pos()?;
+        self.state.move_to(pos);
+        Ok(pos)
+    }
+
+    /// Perform `cmd` on the terminal and track its effect on the cursor.
+    pub fn execute<C: Command>(&mut self, cmd: &C) -> Result<(), Error> {
+        self.term.execute(cmd)?;
+        cmd.track(&mut self.state);
+        Ok(())
+    }
+
+    /// Set the cursor position to the specified coordinates.
+    pub fn set_pos(&mut self, x: i32, y: i32) -> Result<(), Error> {
+        self.term.set_pos(x, y)?;
+        self.state.move_to(Pos::new(x, y));
+        Ok(())
+    }
+
+    /// Get a reference to the underlying terminal.
+    pub fn terminal(&self) -> &Terminal<R, W> {
+        &self.term
+    }
+
+    /// Get a mutable reference to the underlying terminal.
+    ///
+    /// Anything done to the terminal directly is not tracked.
+    pub fn terminal_mut(&mut self) -> &mut Terminal<R, W> {
+        &mut self.term
+    }
+
+    /// Stop tracking, returning the underlying terminal.
+    pub fn into_inner(self) -> Terminal<R, W> {
+        self.term
+    }
+
+    // Track the text in `buf`, skipping escape sequences.
+    fn track_text(&mut self, buf: &[u8]) {
+        let mut text = ::std::mem::take(&mut self.partial);
+        for &byte in buf {
+            let _ = self.parser.push(byte, &mut text);
+        }
+
+        let mut rest = &text[..];
+        loop {
+            let err = match str::from_utf8(rest) {
+                Ok(valid) => return self.print(valid),
+                Err(err) => err,
+            };
+            let (valid, invalid) = rest.split_at(err.valid_up_to());
+            self.print(str::from_utf8(valid).unwrap_or_default());
+            match err.error_len() {
+                Some(len) => {
+                    // Terminals print a replacement character for invalid UTF-8
+                    self.print("\u{FFFD}");
+                    rest = &invalid[len..];
+                }
+                None => {
+                    // Incomplete sequence at the end, the rest follows with the next write
+                    self.partial = invalid.to_vec();
+                    return;
+                }
+            }
+        }
+    }
+
+    fn print(&mut self, text: &str) {
+        print(&mut self.state, text);
+    }
+}
+
+impl<R: Read, W: Write> Write for TrackedTerminal<R, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.term.write(buf)?;
+        self.track_text(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.term.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Empty, Write};
+
+    use super::*;
+
+    fn tracked(pos: Pos, size: (i32, i32)) -> TrackedTerminal<Empty, Vec<u8>> {
+        TrackedTerminal::with_state(
+            Terminal::new(io::empty(), Vec::new()),
+            CursorState::new(pos, size),
+        )
+    }
+
+    fn written(term: TrackedTerminal<Empty, Vec<u8>>) -> Vec<u8> {
+        term.into_inner().into_inner().1
+    }
+
+    #[test]
+    fn text_advances_the_cursor() {
+        let mut term = tracked(Pos::new(0, 0), (80, 24));
+        write!(term, "hello").unwrap();
+        assert_eq!(term.get_pos(), Pos::new(5, 0));
+        assert_eq!(written(term), b"hello");
+    }
+
+    #[test]
+    fn wraps_only_when_the_next_character_is_printed() {
+        let mut term = tracked(Pos::new(0, 0), (10, 5));
+        write!(term, "0123456789").unwrap();
+        assert_eq!(term.get_pos(), Pos::new(9, 0));
+        write!(term, "a").unwrap();
+        assert_eq!(term.get_pos(), Pos::new(1, 1));
+    }
+
+    #[test]
+    fn moving_cancels_a_pending_wrap() {
+        let mut term = tracked(Pos::new(0, 0), (10, 5));
+        write!(term, "0123456789").unwrap();
+        term.execute(&::Column(3)).unwrap();
+        write!(term, "a").unwrap();
+        assert_eq!(term.get_pos(), Pos::new(4, 0));
+    }
+
+    #[test]
+    fn printing_in_the_last_row_scrolls() {
+        let mut term = tracked(Pos::new(0, 4), (10, 5));
+        term.write_all(b"0123456789ab\n\n").unwrap();
+        assert_eq!(term.get_pos(), Pos::new(0, 4));
+    }
+
+    #[test]
+    fn wide_characters_take_two_columns() {
+        let mut term = tracked(Pos::new(0, 0), (10, 5));
+        write!(term, "日本").unwrap();
+        assert_eq!(term.get_pos(), Pos::new(4, 0));
+
+        // A wide character that does not fit into the rest of the line wraps early
+        let mut term = tracked(Pos::new(9, 0), (10, 5));
+        write!(term, "日").unwrap();
+        assert_eq!(term.get_pos(), Pos::new(2, 1));
+    }
+
+    #[test]
+    fn zero_width_characters_do_not_move() {
+        let mut term = tracked(Pos::new(0, 0), (10, 5));
+        write!(term, "e\u{301}").unwrap();
+        assert_eq!(term.get_pos(), Pos::new(1, 0));
+    }
+
+    #[test]
+    fn control_characters() {
+        let mut term = tracked(Pos::new(0, 0), (80, 24));
+        write!(term, "ab\t").unwrap();
+        assert_eq!(term.get_pos(), Pos::new(8, 0));
+        write!(term, "\t\x08").unwrap();
+        assert_eq!(term.get_pos(), Pos::new(15, 0));
+        write!(term, "\r").unwrap();
+        assert_eq!(term.get_pos(), Pos::new(0, 0));
+        term.write_all(b"abc\n").unwrap();
+        assert_eq!(term.get_pos(), Pos::new(0, 1));
+    }
+
+    #[test]
+    fn tabs_stop_at_the_last_column() {
+        let mut term = tracked(Pos::new(75, 0), (80, 24));
+        write!(term, "\t").unwrap();
+        assert_eq!(term.get_pos(), Pos::new(79, 0));
+    }
+
+    #[test]
+    fn escape_sequences_are_skipped() {
+        let mut term = tracked(Pos::new(0, 0), (80, 24));
+        write!(term, "\x1B[31mab\x1B[0m").unwrap();
+        assert_eq!(term.get_pos(), Pos::new(2, 0));
+    }
+
+    #[test]
+    fn utf8_split_over_writes() {
+        let mut term = tracked(Pos::new(0, 0), (80, 24));
+        let euro = "€".as_bytes();
+        term.write_all(&euro[..2]).unwrap();
+        assert_eq!(term.get_pos(), Pos::new(0, 0));
+        term.write_all(&euro[2..]).unwrap();
+        assert_eq!(term.get_pos(), Pos::new(1, 0));
+    }
+
+    #[test]
+    fn invalid_utf8_prints_a_replacement_character() {
+        let mut term = tracked(Pos::new(0, 0), (80, 24));
+        term.write_all(b"a\xFFb").unwrap();
+        assert_eq!(term.get_pos(), Pos::new(3, 0));
+    }
+
+    #[test]
+    fn commands_are_tracked() {
+        let mut term = tracked(Pos::new(0, 0), (80, 24));
+        term.execute(&::Goto(10, 5)).unwrap();
+        term.execute(&::Relative(-3, 2)).unwrap();
+        assert_eq!(term.get_pos(), Pos::new(7, 7));
+        term.execute(&::Right(1000)).unwrap();
+        assert_eq!(term.get_pos(), Pos::new(79, 7));
+        assert_eq!(written(term), b"\x1B[6;11H\x1B[3D\x1B[2B\x1B[1000C");
+    }
+}
@@ -0,0 +1,211 @@
+#![cfg(not(windows))]
+
+extern crate libc;
+extern crate term_cursor as cursor;
+extern crate termios;
+
+use std::ffi::CStr;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::thread;
+use std::time::Duration;
+
+use cursor::{Error, Pos, Terminal};
+
+/// Open a new pty, returning the master and the slave side.
+/// The slave side is put into raw mode, so replies are delivered without waiting for a newline.
+fn open_pty() -> (File, File) {
+    unsafe {
+        let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+        assert!(master >= 0, "posix_openpt failed");
+        assert_eq!(libc::grantpt(master), 0);
+        assert_eq!(libc::unlockpt(master), 0);
+        let name = CStr::from_ptr(libc::ptsname(master)).to_str().unwrap().to_owned();
+        let master = File::from_raw_fd(master);
+
+        let slave = OpenOptions::new().read(true).write(true).open(name).unwrap();
+        let mut tio = termios::Termios::from_fd(slave.as_raw_fd()).unwrap();
+        termios::cfmakeraw(&mut tio);
+        termios::tcsetattr(slave.as_raw_fd(), termios::TCSANOW, &tio).unwrap();
+
+        (master, slave)
+    }
+}
+
+const COLS: i32 = 80;
+const ROWS: i32 = 24;
+
+/// Act as a minimal terminal emulator on the master side of a pty, with 80 columns and 24 rows.
+/// It understands `CUP` and answers `DSR` cursor position requests, both with one-based coordinates.
+/// Like a real terminal, it keeps the cursor within its bounds.
+/// Every reply is preceded by `noise`, simulating input from the user.
+fn emulate(mut master: File, noise: &'static [u8]) {
+    thread::spawn(move || {
+        let (mut row, mut col) = (1, 1);
+        let mut seq = Vec::new();
+        let mut buf = [0u8; 1];
+
+        while let Ok(1) = master.read(&mut buf) {
+            match (seq.len(), buf[0]) {
+                (0, 0x1B) => seq.push(buf[0]),
+                (0, _) => col = (col + 1).min(COLS),
+                (1, b'[') => seq.push(buf[0]),
+                (n, b) if n >= 2 && (b.is_ascii_digit() || b == b';') => seq.push(b),
+                (n, b) if n >= 2 => {
+                    let params = String::from_utf8(seq[2..].to_vec()).unwrap();
+                    let params: Vec<i32> = params
+                        .split(';')
+                        .map(|p| p.parse().unwrap_or(1).max(1))
+                        .collect();
+                    match b {
+                        b'H' => {
+                            row = params[0].min(ROWS);
+                            col = (*params.get(1).unwrap_or(&1)).min(COLS);
+                        }
+                        b'n' => {
+                            master.write_all(noise).unwrap();
+                            write!(master, "\x1B[{};{}R", row, col).unwrap();
+                        }
+                        _ => {}
+                    }
+                    seq.clear();
+                }
+                _ => seq.clear(),
+            }
+        }
+    });
+}
+
+#[test]
+fn set_pos_get_pos_round_trips() {
+    let (master, slave) = open_pty();
+    emulate(master, b"");
+    let mut term = Terminal::new(slave.try_clone().unwrap(), slave);
+
+    for &(x, y) in &[(0, 0), (5, 0), (0, 7), (12, 3), (79, 23)] {
+        term.set_pos(x, y).unwrap();
+        assert_eq!(term.get_pos().unwrap(), Pos::new(x, y));
+    }
+}
+
+#[test]
+fn get_pos_buffers_unrelated_input() {
+    let (master, slave) = open_pty();
+    emulate(master, b"a\x1B[A\x1Bb\x1B[2");
+    let mut term = Terminal::new(slave.try_clone().unwrap(), slave);
+
+    term.set_pos(3, 4).unwrap();
+    assert_eq!(term.get_pos().unwrap(), Pos::new(3, 4));
+    assert_eq!(term.take_pending_input(), b"a\x1B[A\x1Bb\x1B[2");
+}
+
+#[test]
+fn get_pos_timeout_without_reply() {
+    let (_master, slave) = open_pty();
+    let mut term = Terminal::new(slave.try_clone().unwrap(), slave);
+
+    match term.get_pos_timeout(Duration::from_millis(50)) {
+        Err(Error::Timeout) => {}
+        res => panic!("expected a timeout, got {:?}", res),
+    }
+}
+
+#[test]
+fn size_is_asked_from_the_kernel() {
+    let (master, slave) = open_pty();
+    let ws = libc::winsize {
+        ws_row: 30,
+        ws_col: 100,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let res = unsafe { libc::ioctl(slave.as_raw_fd(), libc::TIOCSWINSZ, &ws) };
+    assert_eq!(res, 0);
+    // The emulator has a size of its own, so a size determined through the cursor would be 80x24
+    emulate(master, b"");
+    let mut term = Terminal::new(slave.try_clone().unwrap(), slave);
+
+    assert_eq!(term.size().unwrap(), (100, 30));
+}
+
+#[test]
+fn late_reply_is_not_taken_for_the_next_one() {
+    let (mut master, slave) = open_pty();
+    let mut term = Terminal::new(slave.try_clone().unwrap(), slave);
+
+    match term.get_pos_timeout(Duration::from_millis(50)) {
+        Err(Error::Timeout) => {}
+        res => panic!("expected a timeout, got {:?}", res),
+    }
+    // The reply to the first request arrives late, together with the one to the second request
+    master.write_all(b"\x1B[1;1Rx\x1B[5;6R").unwrap();
+    assert_eq!(term.get_pos().unwrap(), Pos::new(5, 4));
+    assert_eq!(term.take_pending_input(), b"x");
+}
+
+#[test]
+fn size_is_determined_through_the_cursor() {
+    let (master, slave) = open_pty();
+    emulate(master, b"");
+    let mut term = Terminal::new(slave.try_clone().unwrap(), slave);
+
+    term.set_pos(3, 4).unwrap();
+    assert_eq!(term.size().unwrap(), (80, 24));
+    // The cursor is moved back afterwards
+    assert_eq!(term.get_pos().unwrap(), Pos::new(3, 4));
+}
+
+#[test]
+fn checked_moves_report_the_size() {
+    let (master, slave) = open_pty();
+    emulate(master, b"");
+    let mut term = Terminal::new(slave.try_clone().unwrap(), slave);
+
+    match term.set_pos_checked(80, 5) {
+        Err(Error::OutOfBounds { pos, size }) => {
+            assert_eq!(pos, Pos::new(80, 5));
+            assert_eq!(size, (80, 24));
+        }
+        res => panic!("expected an out of bounds error, got {:?}", res),
+    }
+    term.set_pos_checked(79, 23).unwrap();
+    assert_eq!(term.get_pos().unwrap(), Pos::new(79, 23));
+
+    term.set_pos(10, 20).unwrap();
+    match term.move_by_checked(-2, 4) {
+        Err(Error::OutOfBounds { pos, size }) => {
+            assert_eq!(pos, Pos::new(8, 24));
+            assert_eq!(size, (80, 24));
+        }
+        res => panic!("expected an out of bounds error, got {:?}", res),
+    }
+    // The cursor stays where it was
+    assert_eq!(term.get_pos().unwrap(), Pos::new(10, 20));
+    term.move_by_checked(-2, 3).unwrap();
+    assert_eq!(term.get_pos().unwrap(), Pos::new(8, 23));
+}
+
+#[test]
+fn clamped_moves_return_the_position() {
+    let (master, slave) = open_pty();
+    emulate(master, b"");
+    let mut term = Terminal::new(slave.try_clone().unwrap(), slave);
+
+    assert_eq!(term.set_pos_clamped(100, -3).unwrap(), Pos::new(79, 0));
+    assert_eq!(term.get_pos().unwrap(), Pos::new(79, 0));
+    assert_eq!(term.set_pos_clamped(5, 6).unwrap(), Pos::new(5, 6));
+
+    assert_eq!(term.move_by_clamped(-10, 30).unwrap(), Pos::new(0, 23));
+    assert_eq!(term.get_pos().unwrap(), Pos::new(0, 23));
+    assert_eq!(term.move_by_clamped(3, -2).unwrap(), Pos::new(3, 21));
+    assert_eq!(term.get_pos().unwrap(), Pos::new(3, 21));
+}
+
+#[test]
+fn origin_is_zero_based() {
+    let mut term = Terminal::new(&b""[..], Vec::new());
+    term.set_pos(0, 0).unwrap();
+    term.set_pos(4, 2).unwrap();
+    assert_eq!(term.writer(), b"\x1B[1;1H\x1B[3;5H");
+}